@@ -0,0 +1,181 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Compares the masked pattern scanner with the regex translation it
+//! replaced and the single-pass scan of a whole `PatternSet`, on
+//! module-sized buffers.
+
+// `failure_derive` emits its impls inside a function body.
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate log;
+
+#[path = "../src/memlib/findpattern.rs"]
+#[allow(dead_code)]
+mod findpattern;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use findpattern::{Pattern, PatternSet};
+use regex::bytes::Regex;
+use std::hint::black_box;
+
+/// Size of the scanned buffer, about the size of `client.dll`.
+const MODULE_SIZE: usize = 24 << 20;
+
+/// Patterns from `config.json`.
+const PATTERNS: &[(&str, &str)] = &[
+    ("dwClientState", "A1 ? ? ? ? 33 D2 6A 00 6A 00 33 C9 89 B0"),
+    ("dwEntityList", "BB ? ? ? ? 83 FF 01 0F 8C ? ? ? ? 3B F8"),
+    ("dwForceJump", "8B 0D ? ? ? ? 8B D6 8B C1 83 CA 02"),
+    ("m_bDormant", "8A 81 ? ? ? ? C3 32 C0"),
+    ("dwGlowObjectManager", "A1 ? ? ? ? A8 01 75 4B"),
+];
+
+/// The regex translation used before `Pattern`.
+fn generate_regex(raw: &str) -> Regex {
+    let mut res = raw
+        .split_whitespace()
+        .map(|x| match x {
+            "?" => ".".to_string(),
+            x => format!("\\x{}", x),
+        })
+        .collect::<Vec<_>>()
+        .join("");
+    res.insert_str(0, "(?s-u)");
+    Regex::new(&res).unwrap()
+}
+
+/// Pseudo random bytes with the skew of x86 code, the patterns planted at
+/// the end.
+fn module_data() -> Vec<u8> {
+    const COMMON: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0xCC, 0xFF, 0x8B, 0x8B, 0x89, 0xE8, 0x0F, 0x83, 0x85, 0x74, 0x75,
+        0xC3, 0x8D, 0x6A, 0x50, 0x33, 0xA1, 0x8A,
+    ];
+
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let mut data = (0..MODULE_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            match state % 4 {
+                0 | 1 => COMMON[(state >> 8) as usize % COMMON.len()],
+                _ => (state >> 16) as u8,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    plant(
+        &mut data,
+        MODULE_SIZE - 0x1000,
+        PATTERNS.iter().map(|(_, raw)| *raw),
+    );
+    data
+}
+
+/// Write the known bytes of each pattern to `data`, 0x100 bytes apart.
+fn plant<'a>(data: &mut [u8], mut pos: usize, patterns: impl Iterator<Item = &'a str>) {
+    for raw in patterns {
+        let bytes = raw
+            .split_whitespace()
+            .map(|t| u8::from_str_radix(t, 16).unwrap_or(0x12))
+            .collect::<Vec<_>>();
+        data[pos..pos + bytes.len()].copy_from_slice(&bytes);
+        pos += 0x100;
+    }
+}
+
+fn bench_find_pattern(c: &mut Criterion) {
+    let data = module_data();
+
+    let mut group = c.benchmark_group("find_pattern");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(10);
+    for (name, raw) in PATTERNS {
+        let pattern = Pattern::parse(raw).unwrap();
+        let regex = generate_regex(raw);
+
+        group.bench_with_input(BenchmarkId::new("regex", name), &data, |b, data| {
+            b.iter(|| regex.find(black_box(data)).map(|m| m.start()))
+        });
+        group.bench_with_input(BenchmarkId::new("naive", name), &data, |b, data| {
+            b.iter(|| {
+                let last = data.len() - pattern.len();
+                (0..=last).find(|&i| pattern.matches(black_box(&data[i..])))
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("anchored", name), &data, |b, data| {
+            b.iter(|| pattern.find_iter(black_box(data)).next())
+        });
+    }
+    group.finish();
+}
+
+/// All patterns of the shipped config.
+fn config_patterns() -> Vec<String> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config.json");
+    let conf: serde_json::Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+    conf["signatures"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|s| s["pattern"].as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+fn bench_pattern_set(c: &mut Criterion) {
+    let raw = config_patterns();
+    let mut data = module_data();
+    plant(
+        &mut data,
+        MODULE_SIZE - 0x10000,
+        raw.iter().map(String::as_str),
+    );
+
+    let patterns = raw
+        .iter()
+        .map(|r| Pattern::parse(r).unwrap())
+        .collect::<Vec<_>>();
+    let set = PatternSet::new(patterns.clone());
+
+    let mut group = c.benchmark_group("find_patterns");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(10);
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            patterns
+                .iter()
+                .map(|p| p.find_all(black_box(&data)))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("single_pass", |b| b.iter(|| set.find_all(black_box(&data))));
+    group.finish();
+}
+
+criterion_group!(benches, bench_find_pattern, bench_pattern_set);
+criterion_main!(benches);
@@ -17,11 +17,13 @@ fn main() {
 
 fn git_semver() -> String {
     let output = Command::new("git")
-        .args(&["describe", "HEAD"])
+        .args(["describe", "HEAD"])
         .output()
         .unwrap();
     String::from_utf8(output.stdout).unwrap().trim().to_string()
 }
 
 #[cfg(unix)]
-fn main() {}
+fn main() {
+    println!("cargo:rustc-env=GIT_PKG_VERSION_SEMVER={}", git_semver());
+}
@@ -0,0 +1,417 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+extern crate flate2;
+extern crate serde_json;
+
+use self::flate2::Crc;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use crate::config::Signature;
+use crate::memlib::{ElfHeaders, MemorySource, Module, PeHeaders, Rva, Va, DIRECTORY_IAT, PF_W};
+
+/// Version of the cache file, bumped when the meaning of entries changes.
+///
+/// Version 3 hashes the read-only sections of PE images.
+const CACHE_VERSION: u32 = 3;
+
+/// Identity of a module build.
+///
+/// Only built from data that is the same every time the build is loaded,
+/// relocations and writable sections differ between runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleKey {
+    // PE `TimeDateStamp`, 0 for other images.
+    pub timestamp: u32,
+
+    // PE `SizeOfImage`, the module size for other images.
+    pub size: usize,
+
+    // PE `CheckSum`, 0 for other images.
+    pub checksum: u32,
+
+    // CRC32 of the read-only data.
+    pub hash: u32,
+}
+
+impl ModuleKey {
+    pub fn new(module: &Module) -> Self {
+        if let Some(pe) = module.pe() {
+            return ModuleKey {
+                timestamp: pe.timestamp,
+                size: pe.size_of_image,
+                checksum: pe.checksum,
+                hash: pe_hash(module),
+            };
+        }
+
+        ModuleKey {
+            timestamp: 0,
+            size: module.size,
+            checksum: 0,
+            hash: stable_hash(module),
+        }
+    }
+}
+
+/// CRC32 of the sections of a PE image mapped without write access.
+///
+/// The slots the loader patches, base relocations and the import address
+/// table, are hashed as zeros.
+fn pe_hash(module: &Module) -> u32 {
+    let data = &module.data;
+    let headers = match PeHeaders::parse(data) {
+        Some(headers) => headers,
+        None => return 0,
+    };
+    let mut patched = headers.relocations(data);
+    if let Some(iat) = headers.data_directory(DIRECTORY_IAT) {
+        patched.push((iat.rva, iat.size));
+    }
+
+    let mut crc = Crc::new();
+    for s in headers.sections.iter().filter(|s| !s.is_writable()) {
+        let start = s.virtual_address.min(data.len());
+        let end = start.saturating_add(s.mapped_size()).min(data.len());
+        let mut raw = data[start..end].to_vec();
+        for &(rva, len) in &patched {
+            let from = rva.max(start);
+            let to = rva.saturating_add(len).min(end);
+            if from < to {
+                raw[from - start..to - start].fill(0);
+            }
+        }
+        crc.update(&raw);
+    }
+    crc.sum()
+}
+
+/// CRC32 of the data of a non-PE module that does not change between runs.
+///
+/// These are the segments an ELF image maps without write access, or the
+/// regions known not to be writable for other modules.
+fn stable_hash(module: &Module) -> u32 {
+    let data = &module.data;
+    let mut crc = Crc::new();
+    match ElfHeaders::parse(data) {
+        Some(elf) => {
+            let min_vaddr = elf.min_vaddr();
+            for p in elf.segments().filter(|p| p.flags & PF_W == 0) {
+                let start = p.vaddr.saturating_sub(min_vaddr).min(data.len());
+                let end = start.saturating_add(p.filesz).min(data.len());
+                crc.update(&data[start..end]);
+            }
+        }
+        None => {
+            for r in &module.regions {
+                if r.readable && !r.protection.is_some_and(|p| p.write) {
+                    crc.update(&data[r.offset..r.end()]);
+                }
+            }
+        }
+    }
+    crc.sum()
+}
+
+/// A signature result from a previous scan.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    // Build of the module the signature was found in.
+    module: ModuleKey,
+
+    // CRC32 of the signature definition.
+    signature: u32,
+
+    // Result relative to the module base.
+    rva: Rva,
+}
+
+/// Signature results persisted between runs.
+///
+/// An entry is reused as long as neither the module build nor the signature
+/// definition changed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ScanCache {
+    // Cache file version.
+    version: u32,
+
+    // Entries by signature name.
+    entries: BTreeMap<String, CacheEntry>,
+
+    // True to never return cached results, new results are still stored.
+    #[serde(skip)]
+    pub rescan: bool,
+
+    // True if the entries differ from the cache file.
+    #[serde(skip)]
+    dirty: bool,
+
+    // Module identities computed in this run, by module name.
+    #[serde(skip)]
+    modules: HashMap<String, ModuleKey>,
+
+    // Signatures looked up in this run, the only ones saved.
+    #[serde(skip)]
+    seen: HashSet<String>,
+}
+
+/// CRC32 of the signature definition.
+fn signature_hash(sig: &Signature) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(serde_json::to_string(sig).unwrap_or_default().as_bytes());
+    crc.sum()
+}
+
+/// Results that depend on more than the scanned module are never cached.
+fn is_cacheable(sig: &Signature) -> bool {
+    !sig.live && !sig.value && sig.export.is_none()
+}
+
+impl ScanCache {
+    pub fn new() -> Self {
+        ScanCache {
+            version: CACHE_VERSION,
+            ..Default::default()
+        }
+    }
+
+    /// Load the cache file, or start empty if it is missing or outdated.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let file = match File::open(path.as_ref()) {
+            Ok(file) => file,
+            Err(_) => return ScanCache::new(),
+        };
+
+        // Check the version first, the entries of other versions may not parse.
+        let res = serde_json::from_reader::<_, serde_json::Value>(BufReader::new(file)).and_then(
+            |value| match value.get("version") {
+                Some(v) if *v == CACHE_VERSION => {
+                    serde_json::from_value::<ScanCache>(value).map(Some)
+                }
+                _ => Ok(None),
+            },
+        );
+        match res {
+            Ok(Some(cache)) => {
+                debug!("Loaded {} cached signatures", cache.entries.len());
+                cache
+            }
+            Ok(None) => {
+                debug!("Ignoring cache of another version");
+                ScanCache {
+                    dirty: true,
+                    ..ScanCache::new()
+                }
+            }
+            Err(e) => {
+                warn!("Ignoring invalid cache {}: {}", path.as_ref().display(), e);
+                ScanCache {
+                    dirty: true,
+                    ..ScanCache::new()
+                }
+            }
+        }
+    }
+
+    /// Write the entries of the signatures used in this run.
+    ///
+    /// Does nothing if no entry changed.
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let seen = &self.seen;
+        let count = self.entries.len();
+        self.entries.retain(|name, _| seen.contains(name));
+        if !self.dirty && self.entries.len() == count {
+            return Ok(());
+        }
+
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(file, self).map_err(io::Error::from)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Identity of `module`, computed once per run.
+    fn module_key(&mut self, module: &Module) -> ModuleKey {
+        self.modules
+            .entry(module.name.clone())
+            .or_insert_with(|| ModuleKey::new(module))
+            .clone()
+    }
+
+    /// Get the cached result of `sig`, if the module and signature are unchanged.
+    pub fn get<S: MemorySource + ?Sized>(&mut self, sig: &Signature, source: &S) -> Option<usize> {
+        self.seen.insert(sig.name.clone());
+        if self.rescan || !is_cacheable(sig) {
+            return None;
+        }
+
+        let module = source.get_module(&sig.module)?;
+        let key = self.module_key(&module);
+        let entry = self.entries.get(&sig.name)?;
+        if entry.module != key || entry.signature != signature_hash(sig) {
+            debug!("Cached {} is outdated", sig.name);
+            return None;
+        }
+
+        Some(if sig.relative {
+            entry.rva.0
+        } else {
+            module.va(entry.rva).0
+        })
+    }
+
+    /// Store the result of `sig`.
+    pub fn insert<S: MemorySource + ?Sized>(&mut self, sig: &Signature, source: &S, result: usize) {
+        if !is_cacheable(sig) {
+            return;
+        }
+        let module = match source.get_module(&sig.module) {
+            Some(module) => module,
+            None => return,
+        };
+
+        let rva = if sig.relative {
+            Rva(result)
+        } else {
+            match module.rva(Va(result)) {
+                Some(rva) => rva,
+                None => {
+                    debug!("Not caching {}, it is outside of {}", sig.name, module.name);
+                    return;
+                }
+            }
+        };
+        let entry = CacheEntry {
+            module: self.module_key(&module),
+            signature: signature_hash(sig),
+            rva,
+        };
+        if self.entries.get(&sig.name) != Some(&entry) {
+            self.entries.insert(sig.name.clone(), entry);
+            self.dirty = true;
+        }
+    }
+
+    /// Forget the result of `sig`, e.g. after a failed scan.
+    pub fn remove(&mut self, sig: &Signature) {
+        self.dirty |= self.entries.remove(&sig.name).is_some();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE_BASE: usize = 0x1000_0000;
+
+    fn put(data: &mut [u8], pos: usize, bytes: &[u8]) {
+        data[pos..pos + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// A PE32 file with a `.text` section holding a relocated pointer and an
+    /// import address table, a `.reloc` section and a writable `.data`
+    /// section.
+    fn pe_file() -> Vec<u8> {
+        let mut file = vec![0u8; 0x800];
+        put(&mut file, 0, b"MZ");
+        put(&mut file, 0x3C, &0x40u32.to_le_bytes());
+        put(&mut file, 0x40, b"PE\0\0");
+        put(&mut file, 0x44, &0x14Cu16.to_le_bytes());
+        put(&mut file, 0x46, &3u16.to_le_bytes());
+        put(&mut file, 0x54, &0xE0u16.to_le_bytes());
+
+        let opt = 0x58;
+        put(&mut file, opt, &0x10Bu16.to_le_bytes());
+        put(&mut file, opt + 28, &(IMAGE_BASE as u32).to_le_bytes());
+        put(&mut file, opt + 32, &0x1000u32.to_le_bytes());
+        put(&mut file, opt + 36, &0x200u32.to_le_bytes());
+        put(&mut file, opt + 56, &0x4000u32.to_le_bytes());
+        put(&mut file, opt + 60, &0x200u32.to_le_bytes());
+        put(&mut file, opt + 92, &16u32.to_le_bytes());
+        // Base relocation and import address table directories.
+        put(
+            &mut file,
+            opt + 96 + 5 * 8,
+            &[0x00, 0x20, 0, 0, 12, 0, 0, 0],
+        );
+        put(
+            &mut file,
+            opt + 96 + 12 * 8,
+            &[0x00, 0x11, 0, 0, 8, 0, 0, 0],
+        );
+
+        let sections: [(&[u8], u32, u32, u32); 3] = [
+            (b".text", 0x1000, 0x200, 0x6000_0020),
+            (b".reloc", 0x2000, 0x400, 0x4200_0040),
+            (b".data", 0x3000, 0x600, 0xC000_0040),
+        ];
+        for (i, &(name, rva, raw, flags)) in sections.iter().enumerate() {
+            let pos = opt + 0xE0 + i * 40;
+            put(&mut file, pos, name);
+            put(&mut file, pos + 8, &0x200u32.to_le_bytes());
+            put(&mut file, pos + 12, &rva.to_le_bytes());
+            put(&mut file, pos + 16, &0x200u32.to_le_bytes());
+            put(&mut file, pos + 20, &raw.to_le_bytes());
+            put(&mut file, pos + 36, &flags.to_le_bytes());
+        }
+
+        // mov eax, [.data]
+        put(&mut file, 0x200, &[0xA1]);
+        put(
+            &mut file,
+            0x201,
+            &(IMAGE_BASE as u32 + 0x3000).to_le_bytes(),
+        );
+        // IMAGE_REL_BASED_HIGHLOW at 0x1001.
+        put(
+            &mut file,
+            0x400,
+            &[0x00, 0x10, 0, 0, 12, 0, 0, 0, 0x01, 0x30, 0, 0],
+        );
+        file
+    }
+
+    fn key(file: &[u8], base: usize) -> ModuleKey {
+        ModuleKey::new(&Module::from_pe_file("test.dll", file, Some(base)).unwrap())
+    }
+
+    #[test]
+    fn pe_key_ignores_loader_patches() {
+        let file = pe_file();
+        let key = key(&file, IMAGE_BASE);
+        assert_eq!(key.size, 0x4000);
+        assert_eq!(self::key(&file, 0x2000_0000), key);
+
+        // Bound imports and writable data.
+        let mut patched = file.clone();
+        put(&mut patched, 0x300, &0x7700_1234u32.to_le_bytes());
+        put(&mut patched, 0x600, &[1, 2, 3, 4]);
+        assert_eq!(self::key(&patched, 0x2000_0000), key);
+
+        let mut rebuilt = file;
+        put(&mut rebuilt, 0x210, &[0xC3]);
+        assert_ne!(self::key(&rebuilt, IMAGE_BASE), key);
+    }
+}
@@ -32,6 +32,10 @@ pub enum ConfigError {
 
 pub type Result<T> = ::std::result::Result<T, ConfigError>;
 
+fn default_true() -> bool {
+    true
+}
+
 // This struct represents a signature.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Signature {
@@ -39,8 +43,54 @@ pub struct Signature {
     pub name: String,
 
     // Signature pattern.
+    #[serde(default)]
     pub pattern: String,
 
+    // Mask of a `pattern` in code syntax, e.g. `x????`.
+    #[serde(default)]
+    pub mask: Option<String>,
+
+    // If true, fail if `pattern` matches more than once.
+    #[serde(default = "default_true")]
+    pub expect_unique: bool,
+
+    // Use the n-th match of `pattern`, counting from 0, instead of
+    // requiring a unique one.
+    #[serde(default)]
+    pub match_index: Option<usize>,
+
+    // Export name, used instead of `pattern` if given. A forwarded export
+    // resolves into the module it is forwarded to, so `offsets` and
+    // `relative` then apply to that module instead of `module`.
+    #[serde(default)]
+    pub export: Option<String>,
+
+    // Class name whose vtable is used instead of `pattern` if given.
+    #[serde(default)]
+    pub vtable: Option<String>,
+
+    // String literal whose first code reference is used instead of
+    // `pattern` if given.
+    #[serde(default)]
+    pub string: Option<String>,
+
+    // If true, `string` is searched as UTF-16.
+    #[serde(default)]
+    pub string_wide: bool,
+
+    // If true, use the start of the function containing the reference.
+    #[serde(default)]
+    pub function_start: bool,
+
+    // If true, follow `offsets` through memory reads on the target instead
+    // of the module data, so pointers may leave the module.
+    #[serde(default)]
+    pub live: bool,
+
+    // If true, the result is the pointer-sized value at the final address.
+    #[serde(default)]
+    pub value: bool,
+
     // Module name.
     pub module: String,
 
@@ -52,7 +102,8 @@ pub struct Signature {
     #[serde(default)]
     pub extra: isize,
 
-    // If true, subtract module base from result.
+    // If true, subtract module base from result. Fails if the result lies
+    // outside the module, e.g. after a `live` chain left it.
     #[serde(default)]
     pub relative: bool,
 
@@ -87,6 +138,16 @@ impl Default for Signature {
         Signature {
             name: "".to_string(),
             pattern: "".to_string(),
+            mask: None,
+            expect_unique: true,
+            match_index: None,
+            export: None,
+            vtable: None,
+            string: None,
+            string_wide: false,
+            function_start: false,
+            live: false,
+            value: false,
             module: "".to_string(),
             offsets: vec![],
             extra: 0,
@@ -114,6 +175,10 @@ pub struct Config {
     // `Vec` containing the `Netvar`s.
     #[serde(default)]
     pub netvars: Vec<Netvar>,
+
+    // Modules whose interface registry should be dumped.
+    #[serde(default)]
+    pub interfaces: Vec<String>,
 }
 
 impl Default for Config {
@@ -123,6 +188,7 @@ impl Default for Config {
             filename: "csgo".to_string(),
             signatures: vec![],
             netvars: vec![],
+            interfaces: vec![],
         }
     }
 }
@@ -20,12 +20,10 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-#![allow(clippy::double_parens)]
-
-use crate::memlib::Module;
-use nom::*;
+use crate::memlib::{MemoryReader, Module, Va};
 
 use crate::games::csgo::table::RecvTable;
+use crate::games::csgo::MAX_NAME_LEN;
 
 #[derive(Debug, PartialEq)]
 pub struct ClientClass {
@@ -36,40 +34,19 @@ pub struct ClientClass {
 
 #[derive(Debug)]
 pub struct ClientClassIterator<'a> {
-    next_offset: usize,
+    next_offset: Va,
     module: &'a Module,
 }
 
 impl ClientClass {
-    // offset_name, offset_table, offset_next, id
-    #[rustfmt::skip]
-    named!(
-        parse_raw<(usize, usize, usize, i32)>,
-        do_parse!(
-            take!(8)              >>
-            offset_name  : le_u32 >>
-            offset_table : le_u32 >>
-            offset_next  : le_u32 >>
-            id           : le_i32 >>
-            ((
-                offset_name as usize,
-                offset_table as usize,
-                offset_next as usize,
-                id,
-            ))
-        )
-    );
-
-    fn parse(base: usize, module: &Module) -> Option<(ClientClass, usize)> {
+    fn parse(base: Va, module: &Module) -> Option<(ClientClass, Va)> {
         debug!("Starting to parse ClientClass at {:#x}", base);
-        let data = module.get_slice(base, 0x18, false)?;
-        let (_, (offset_name, offset_table, offset_next, id)) =
-            ClientClass::parse_raw(&data).ok()?;
+        let offset_name = module.read_pointer(base + 0x8, 4).ok()?;
+        let offset_table = module.read_pointer(base + 0xC, 4).ok()?;
+        let offset_next = module.read_pointer(base + 0x10, 4).ok()?;
+        let id = module.read::<i32>(base + 0x14).ok()?;
 
-        let name = crate::helpers::parse_string(module.get(offset_name, false)?)
-            .ok()?
-            .1
-            .to_string();
+        let name = module.read_cstring(offset_name, MAX_NAME_LEN).ok()?;
         debug!("Found ClientClass '{}' at {:#x}", name, base);
 
         let cc = ClientClass {
@@ -83,7 +60,7 @@ impl ClientClass {
 }
 
 impl<'a> ClientClassIterator<'a> {
-    pub fn new(next_offset: usize, module: &'a Module) -> Self {
+    pub fn new(next_offset: Va, module: &'a Module) -> Self {
         Self {
             next_offset,
             module,
@@ -95,7 +72,7 @@ impl<'a> Iterator for ClientClassIterator<'a> {
     type Item = ClientClass;
 
     fn next(&mut self) -> Option<ClientClass> {
-        if self.next_offset == 0 {
+        if self.next_offset == Va(0) {
             return None;
         }
 
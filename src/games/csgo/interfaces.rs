@@ -0,0 +1,262 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::games::csgo::MAX_NAME_LEN;
+use crate::memlib::{MemoryReader, Module, Rva, Va};
+use std::collections::HashSet;
+
+/// Upper bound for the length of the `InterfaceReg` list.
+const MAX_INTERFACES: usize = 0x1000;
+
+/// Bytes of `CreateInterface` searched for the `s_pInterfaceRegs` load.
+const SEARCH_SIZE: usize = 0x20;
+
+/// A registered interface, from an `InterfaceReg {create_fn, name, next}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    // Interface name with version suffix, e.g. `VClient018`.
+    pub name: String,
+
+    // Address of the factory function.
+    pub create_fn: Va,
+
+    // Address of the instance returned by the factory, if static.
+    pub instance: Option<Va>,
+}
+
+#[derive(Debug)]
+pub struct InterfaceIterator<'a> {
+    next_offset: Va,
+    pointer_width: usize,
+    visited: HashSet<Va>,
+    module: &'a Module,
+}
+
+/// The target of the `rel32` operand at `rva`, relative to the end of the
+/// instruction at `end`.
+fn rel32_target(module: &Module, rva: Rva, end: Rva) -> Option<Va> {
+    let rel: i32 = module.read(module.va(rva)).ok()?;
+    Some(module.va(end.offset(rel as isize)))
+}
+
+/// Length of a ModRM operand with its SIB byte and displacement.
+fn modrm_len(code: &[u8]) -> Option<usize> {
+    let modrm = *code.first()?;
+    let (mode, rm) = (modrm >> 6, modrm & 7);
+    let sib = mode != 3 && rm == 4;
+    let disp = match mode {
+        0 if rm == 5 => 4,
+        0 if sib && code.get(1)? & 7 == 5 => 4,
+        1 => 1,
+        2 => 4,
+        _ => 0,
+    };
+    Some(1 + sib as usize + disp)
+}
+
+/// Find the address of the `s_pInterfaceRegs` global used by the code at
+/// `rva`, following up to two leading `jmp`s.
+///
+/// Decodes the usual prologue instructions and looks for `mov reg, [abs32]`
+/// on x86 and `mov reg, [rip + rel32]` on x64. A `jmp` is followed right
+/// away, anything after it is never looked at. Gives up at a `ret` or an
+/// instruction it does not decode.
+fn find_list_global(module: &Module, mut rva: Rva, pointer_width: usize) -> Option<Va> {
+    for _ in 0..3 {
+        let code = module.get_slice(rva, SEARCH_SIZE)?;
+        let abs32 = |pos: usize| {
+            let raw: u32 = module.read(module.va(rva + pos)).ok()?;
+            Some(Va(raw as usize))
+        };
+
+        let mut i = 0;
+        let target = loop {
+            let c = code.get(i..)?;
+            let rex = (pointer_width == 8 && c.first()? & 0xF0 == 0x40) as usize;
+            let op = &c[rex..];
+            match *op.first()? {
+                // mov eax, [abs32]
+                0xA1 if pointer_width == 4 => return abs32(i + 1),
+                // mov r32, [abs32] or mov r64, [rip + rel32]
+                0x8B if op.get(1)? & 0xC7 == 0x05 => {
+                    let disp = i + rex + 2;
+                    return match pointer_width {
+                        4 => abs32(disp),
+                        _ => rel32_target(module, rva + disp, rva + (disp + 4)),
+                    };
+                }
+                // push, pop
+                0x50..=0x5F => i += rex + 1,
+                // mov, xor, test, add and sub with a ModRM operand
+                0x03 | 0x2B | 0x31 | 0x33 | 0x85 | 0x89 | 0x8B => {
+                    i += rex + 1 + modrm_len(&op[1..])?
+                }
+                // add, sub, cmp with imm8
+                0x83 => i += rex + 2 + modrm_len(&op[1..])?,
+                0xE9 => break rel32_target(module, rva + (i + 1), rva + (i + 5))?,
+                0xEB => break module.va(rva + (i + 2)).offset(*op.get(1)? as i8 as isize),
+                _ => return None,
+            }
+        };
+
+        rva = module.rva(target)?;
+        debug!("Following jmp to {:#X}", rva);
+    }
+    None
+}
+
+/// Resolve the instance a factory returns if it is a static object.
+///
+/// Handles `mov eax, imm32; ret` on x86 and `lea rax, [rip + rel32]; ret`
+/// on x64, as well as reads of an instance pointer inside the module.
+fn resolve_instance(module: &Module, create_fn: Va, pointer_width: usize) -> Option<Va> {
+    let rva = module.rva(create_fn)?;
+    let code: [u8; 8] = module.read_array(create_fn).ok()?;
+
+    let addr = match (pointer_width, code) {
+        (4, [0xB8, a, b, c, d, 0xC3, ..]) => Va(u32::from_le_bytes([a, b, c, d]) as usize),
+        (4, [0xA1, a, b, c, d, 0xC3, ..]) => {
+            let ptr = Va(u32::from_le_bytes([a, b, c, d]) as usize);
+            module.read_pointer(ptr, pointer_width).ok()?
+        }
+        (8, [0x48, 0x8D, 0x05, _, _, _, _, 0xC3]) => rel32_target(module, rva + 3, rva + 7)?,
+        (8, [0x48, 0x8B, 0x05, _, _, _, _, 0xC3]) => {
+            let ptr = rel32_target(module, rva + 3, rva + 7)?;
+            module.read_pointer(ptr, pointer_width).ok()?
+        }
+        _ => return None,
+    };
+
+    Some(addr).filter(|&a| module.contains(a))
+}
+
+impl Interface {
+    fn parse(base: Va, module: &Module, pointer_width: usize) -> Option<(Interface, Va)> {
+        debug!("Starting to parse InterfaceReg at {:#x}", base);
+        let create_fn = module.read_pointer(base, pointer_width).ok()?;
+        let offset_name = module
+            .read_pointer(base + pointer_width, pointer_width)
+            .ok()?;
+        let offset_next = module
+            .read_pointer(base + 2 * pointer_width, pointer_width)
+            .ok()?;
+
+        let name = module.read_cstring(offset_name, MAX_NAME_LEN).ok()?;
+        debug!("Found InterfaceReg '{}' at {:#x}", name, base);
+
+        let iface = Interface {
+            name,
+            create_fn,
+            instance: resolve_instance(module, create_fn, pointer_width),
+        };
+
+        Some((iface, offset_next))
+    }
+}
+
+impl<'a> InterfaceIterator<'a> {
+    pub fn new(next_offset: Va, pointer_width: usize, module: &'a Module) -> Self {
+        Self {
+            next_offset,
+            pointer_width,
+            visited: HashSet::new(),
+            module,
+        }
+    }
+
+    /// Locate `s_pInterfaceRegs` through the `CreateInterface` export.
+    pub fn from_module(module: &'a Module, pointer_width: usize) -> Option<Self> {
+        let export = module.pe()?.export("CreateInterface")?;
+        debug!("CreateInterface of {} at {:#X}", module.name, export.rva);
+
+        let global = find_list_global(module, Rva(export.rva), pointer_width)?;
+        debug!("s_pInterfaceRegs at {:#X}", global);
+        let first = module.read_pointer(global, pointer_width).ok()?;
+
+        Some(Self::new(first, pointer_width, module))
+    }
+}
+
+impl<'a> Iterator for InterfaceIterator<'a> {
+    type Item = Interface;
+
+    fn next(&mut self) -> Option<Interface> {
+        if self.next_offset == Va(0)
+            || self.visited.len() >= MAX_INTERFACES
+            || !self.visited.insert(self.next_offset)
+        {
+            return None;
+        }
+
+        let (iface, next) = Interface::parse(self.next_offset, self.module, self.pointer_width)?;
+
+        self.next_offset = next;
+        Some(iface)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Va = Va(0x1000_0000);
+
+    /// A module with `code` at each `(rva, code)`.
+    fn module(code: &[(usize, &[u8])]) -> Module {
+        let mut data = vec![0xCCu8; 0x200];
+        for &(rva, bytes) in code {
+            data[rva..rva + bytes.len()].copy_from_slice(bytes);
+        }
+        Module::new("test.dll", BASE, data)
+    }
+
+    #[test]
+    fn follows_thunk_before_decoding_past_it() {
+        // push ebp; mov ebp, esp; pop ebp; jmp +0xA1, then a decoy load.
+        let thunk: &[u8] = &[
+            0x55, 0x8B, 0xEC, 0x5D, 0xE9, 0xA1, 0x00, 0x00, 0x00, 0xA1, 0x44, 0x33, 0x22, 0x11,
+        ];
+        // push ebp; mov ebp, esp; push esi; mov esi, [0x12345678]
+        let target: &[u8] = &[0x55, 0x8B, 0xEC, 0x56, 0x8B, 0x35, 0x78, 0x56, 0x34, 0x12];
+        let module = module(&[(0, thunk), (0xAA, target)]);
+        assert_eq!(find_list_global(&module, Rva(0), 4), Some(Va(0x1234_5678)));
+    }
+
+    #[test]
+    fn ignores_loads_after_ret() {
+        let module = module(&[(0, &[0x55, 0x5D, 0xC3, 0xA1, 0x44, 0x33, 0x22, 0x11])]);
+        assert_eq!(find_list_global(&module, Rva(0), 4), None);
+    }
+
+    #[test]
+    fn finds_rip_relative_load() {
+        // sub rsp, 0x28; mov r9, [rip + 0x100]
+        let code: &[u8] = &[
+            0x48, 0x83, 0xEC, 0x28, 0x4C, 0x8B, 0x0D, 0x00, 0x01, 0x00, 0x00,
+        ];
+        let module = module(&[(0x10, code)]);
+        assert_eq!(
+            find_list_global(&module, Rva(0x10), 8),
+            Some(BASE + (0x10 + code.len() + 0x100))
+        );
+    }
+}
@@ -21,10 +21,15 @@
 // SOFTWARE.
 
 mod clientclass;
+mod interfaces;
 mod netvars;
 mod prop;
 mod table;
 
 pub use self::clientclass::ClientClassIterator;
+pub use self::interfaces::InterfaceIterator;
 pub use self::netvars::NetvarManager;
 pub use self::table::RecvTable;
+
+/// Upper bound for the length of names read from game structures.
+const MAX_NAME_LEN: usize = 0x100;
@@ -22,7 +22,7 @@
 
 #![allow(clippy::double_parens)]
 
-use crate::memlib::Process;
+use crate::memlib::{MemorySource, Rva};
 use std::collections::BTreeMap;
 
 use crate::games::csgo;
@@ -33,13 +33,12 @@ pub struct NetvarManager {
 }
 
 impl NetvarManager {
-    pub fn new(first: usize, process: &Process) -> Option<Self> {
-        let module = process
-            .get_module("client.dll")
-            .or_else(|| process.get_module("client_panorama.dll"))?;
-        debug!("First ClientClass at {:#X}", first);
+    /// Walk the `ClientClass` list starting at `first` in `module`.
+    pub fn new<S: MemorySource + ?Sized>(first: Rva, module: &str, source: &S) -> Option<Self> {
+        let module = source.get_module(module)?;
+        debug!("First ClientClass at {:#X} in {}", first, module.name);
 
-        let classes = csgo::ClientClassIterator::new(first + module.base, &module);
+        let classes = csgo::ClientClassIterator::new(module.va(first), &module);
         let tables = classes
             .map(|c| (c.table.name.clone(), c.table))
             .collect::<BTreeMap<_, _>>();
@@ -20,13 +20,10 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-#![allow(clippy::double_parens)]
-
-use crate::memlib::Module;
-use nom::*;
-use std::str;
+use crate::memlib::{MemoryReader, Module, Va};
 
 use crate::games::csgo::table::RecvTable;
+use crate::games::csgo::MAX_NAME_LEN;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct RecvProp {
@@ -37,39 +34,20 @@ pub struct RecvProp {
 
 #[derive(Debug)]
 pub struct RecvPropIterator<'a> {
-    base: usize,
+    base: Va,
     current: usize,
     max: usize,
     module: &'a Module,
 }
 
 impl RecvProp {
-    // offset_name, offset_table, value
-    #[rustfmt::skip]
-    named!(
-        parse_raw<(usize, usize, i32)>,
-        do_parse!(
-            offset_name  : le_u32 >>
-            take!(0x24)           >>
-            offset_table : le_u32 >>
-            value        : le_i32 >>
-            ((
-                offset_name as usize,
-                offset_table as usize,
-                value,
-            ))
-        )
-    );
-
-    fn parse(base: usize, module: &Module) -> Option<RecvProp> {
+    fn parse(base: Va, module: &Module) -> Option<RecvProp> {
         trace!("Starting to parse RecvProp at {:#x}", base);
-        let data = module.get_slice(base, 0x30, false)?;
-        let (_, (offset_name, offset_table, value)) = RecvProp::parse_raw(&data).ok()?;
+        let offset_name = module.read_pointer(base, 4).ok()?;
+        let offset_table = module.read_pointer(base + 0x28, 4).ok()?;
+        let value = module.read::<i32>(base + 0x2C).ok()?;
 
-        let name = crate::helpers::parse_string(module.get(offset_name, false)?)
-            .ok()?
-            .1
-            .to_string();
+        let name = module.read_cstring(offset_name, MAX_NAME_LEN).ok()?;
         trace!(
             "Found RecvProp '{}' at {:#x}, value {:#x} childtable {:#X}",
             name,
@@ -79,7 +57,7 @@ impl RecvProp {
         );
 
         let table = match offset_table {
-            0 => None,
+            Va(0) => None,
             _ => RecvTable::parse(offset_table, module),
         };
 
@@ -96,17 +74,14 @@ impl RecvProp {
         }
 
         match self.table {
-            Some(ref table) => match table.get_offset(name) {
-                Some(o) => Some(o + self.offset),
-                _ => None,
-            },
+            Some(ref table) => table.get_offset(name).map(|o| o + self.offset),
             _ => None,
         }
     }
 }
 
 impl<'a> RecvPropIterator<'a> {
-    pub fn new(base: usize, max: usize, module: &'a Module) -> Self {
+    pub fn new(base: Va, max: usize, module: &'a Module) -> Self {
         Self {
             base,
             current: 0,
@@ -20,13 +20,10 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-#![allow(clippy::double_parens)]
-
-use crate::memlib::Module;
-use nom::*;
-use std::str;
+use crate::memlib::{MemoryReader, Module, Va};
 
 use super::prop::{RecvProp, RecvPropIterator};
+use super::MAX_NAME_LEN;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct RecvTable {
@@ -35,36 +32,17 @@ pub struct RecvTable {
 }
 
 impl RecvTable {
-    // offset_name, offset_props, num_props
-    #[rustfmt::skip]
-    named!(
-        parse_raw<(usize, usize, usize)>,
-        do_parse!(
-            offset_props: le_u32 >>
-            num_props: le_u32    >>
-            take!(4)             >>
-            offset_name: le_u32  >>
-            ((
-                offset_name as usize,
-                offset_props as usize,
-                num_props as usize,
-            ))
-        )
-    );
-
-    pub fn parse(base: usize, module: &Module) -> Option<Self> {
+    pub fn parse(base: Va, module: &Module) -> Option<Self> {
         trace!("Starting to parse RecvTable at {:#x}", base);
-        if base == 0 {
+        if base == Va(0) {
             return None;
         }
 
-        let data = module.get_slice(base, 0x10, false)?;
-        let (_, (offset_name, offset_props, num_props)) = Self::parse_raw(&data).ok()?;
+        let offset_props = module.read_pointer(base, 4).ok()?;
+        let num_props = module.read::<u32>(base + 0x4).ok()? as usize;
+        let offset_name = module.read_pointer(base + 0xC, 4).ok()?;
 
-        let name = crate::helpers::parse_string(module.get(offset_name, false)?)
-            .ok()?
-            .1
-            .to_string();
+        let name = module.read_cstring(offset_name, MAX_NAME_LEN).ok()?;
         trace!("Found RecvTable '{}' at {:#x}", name, base);
 
         Some(Self {
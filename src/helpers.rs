@@ -24,3 +24,8 @@ named!(
     pub parse_string<&str>,
     map_res!(take_until_and_consume!("\0"), ::std::str::from_utf8)
 );
+
+/// File name component of a Windows or Unix path.
+pub fn file_name(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
@@ -22,6 +22,9 @@
 
 //! HazeDumper rewritten in Rust!
 
+// `failure_derive` emits its impls inside a function body.
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate failure;
 #[macro_use]
@@ -35,6 +38,7 @@ extern crate simplelog;
 extern crate structopt;
 extern crate structopt_derive;
 
+mod cache;
 mod config;
 mod games;
 mod helpers;
@@ -42,16 +46,27 @@ mod memlib;
 mod output;
 mod sigscan;
 
-use std::collections::BTreeMap;
-use std::fs::OpenOptions;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions};
+use std::io::Read;
 use std::process::exit;
+#[cfg(any(windows, target_os = "linux"))]
+use std::thread;
+#[cfg(any(windows, target_os = "linux"))]
+use std::time::{Duration, Instant};
 
+use crate::cache::ScanCache;
 use crate::config::Config;
+use crate::memlib::{MemorySource, Rva};
 use simplelog::*;
 use structopt::StructOpt;
 
 type Map<T> = BTreeMap<String, T>;
 
+/// Delay between two polls of `--wait`.
+#[cfg(any(windows, target_os = "linux"))]
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 #[derive(StructOpt, Debug)]
 #[structopt(
     name = "hazedumper",
@@ -78,6 +93,170 @@ struct Opt {
     /// Optional parameter, overrides the target executable.
     #[structopt(short = "t", long = "target", help = "Process name")]
     target: Option<String>,
+
+    /// A flag, matches the target process name as a regex.
+    #[structopt(long = "regex", help = "Match the process name as a regex")]
+    regex: bool,
+
+    /// Optional parameter, attach to a process id instead of a name.
+    #[structopt(
+        short = "p",
+        long = "pid",
+        conflicts_with_all = &["dir", "dump", "replay"],
+        help = "Process id"
+    )]
+    pid: Option<u32>,
+
+    /// A flag, waits for the process and its modules instead of failing.
+    #[structopt(
+        short = "w",
+        long = "wait",
+        help = "Wait for the process and every module used by the config"
+    )]
+    wait: bool,
+
+    /// Optional parameter, gives up waiting after this many seconds.
+    #[structopt(
+        long = "timeout",
+        requires = "wait",
+        help = "Timeout in seconds for --wait, waits indefinitely if not given"
+    )]
+    timeout: Option<u64>,
+
+    /// Optional parameter, scan module files instead of a process.
+    #[structopt(
+        short = "d",
+        long = "dir",
+        conflicts_with_all = &["dump", "replay"],
+        help = "Directory of module files to scan instead of a process"
+    )]
+    dir: Option<String>,
+
+    /// Optional parameter, scan a minidump or core file instead of a process.
+    #[structopt(
+        long = "dump",
+        conflicts_with = "replay",
+        help = "Minidump (.dmp) or ELF core file to scan instead of a process"
+    )]
+    dump: Option<String>,
+
+    /// Optional parameter, replay a snapshot instead of a process.
+    #[structopt(
+        long = "replay",
+        help = "Snapshot archive to scan instead of a process"
+    )]
+    replay: Option<String>,
+
+    /// Optional parameter, base addresses for modules loaded from `dir`.
+    #[structopt(
+        long = "base",
+        number_of_values = 1,
+        help = "Map a module file at a base address, e.g. client.dll=0x10000000",
+        parse(try_from_str = parse_base)
+    )]
+    bases: Vec<(String, usize)>,
+
+    /// Optional parameter, the scan cache file.
+    #[structopt(long = "cache", help = "Scan cache file [hazedumper.cache]")]
+    cache: Option<String>,
+
+    /// A flag, ignores cached signature results but still updates the cache.
+    #[structopt(
+        long = "rescan",
+        help = "Ignore cached results and rescan every signature"
+    )]
+    rescan: bool,
+
+    /// A flag, neither reads nor writes the scan cache.
+    #[structopt(
+        long = "no-cache",
+        conflicts_with = "cache",
+        help = "Do not read or write the scan cache"
+    )]
+    no_cache: bool,
+
+    /// Optional subcommand, scans the target if not given.
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Save every module referenced by the config into a snapshot archive.
+    #[structopt(name = "snapshot")]
+    Snapshot {
+        /// Archive file to write.
+        #[structopt(help = "Output archive file")]
+        output: String,
+    },
+
+    /// List every module of the target.
+    #[structopt(name = "list-modules")]
+    ListModules {
+        /// Print JSON instead of a table.
+        #[structopt(long = "json", help = "Print the modules as JSON")]
+        json: bool,
+    },
+
+    /// Print the PE headers, exports and imports of a module.
+    #[structopt(name = "module-info")]
+    ModuleInfo {
+        /// Module name, e.g. `client.dll`.
+        #[structopt(help = "Module name")]
+        module: String,
+
+        /// Print JSON instead of text.
+        #[structopt(long = "json", help = "Print the module info as JSON")]
+        json: bool,
+    },
+
+    /// List every class vtable of a module found through RTTI.
+    #[structopt(name = "list-vtables")]
+    ListVTables {
+        /// Module name, e.g. `client.dll`.
+        #[structopt(help = "Module name")]
+        module: String,
+
+        /// Print JSON instead of a table.
+        #[structopt(long = "json", help = "Print the vtables as JSON")]
+        json: bool,
+    },
+
+    /// List every running process.
+    #[structopt(name = "list-processes")]
+    ListProcesses {
+        /// Print JSON instead of a table.
+        #[structopt(long = "json", help = "Print the processes as JSON")]
+        json: bool,
+    },
+
+    /// Print a pattern in IDA, x64dbg and code syntax.
+    #[structopt(name = "convert-pattern")]
+    ConvertPattern {
+        /// Pattern in any supported syntax.
+        #[structopt(help = "Pattern, e.g. \"A1 ? ? ? ?\" or \"A1????????\"")]
+        pattern: String,
+
+        /// Mask of a pattern in code syntax.
+        #[structopt(long = "mask", help = "Mask of a code syntax pattern, e.g. x????")]
+        mask: Option<String>,
+
+        /// Print JSON instead of text.
+        #[structopt(long = "json", help = "Print the pattern as JSON")]
+        json: bool,
+    },
+}
+
+/// Parse a `module=address` pair.
+fn parse_base(raw: &str) -> Result<(String, usize), String> {
+    let mut parts = raw.splitn(2, '=');
+    let name = parts.next().unwrap_or_default();
+    let addr = parts
+        .next()
+        .ok_or_else(|| format!("Expected module=address, got {}", raw))?;
+    let addr = addr.trim_start_matches("0x").trim_start_matches("0X");
+    let addr = usize::from_str_radix(addr, 16).map_err(|e| e.to_string())?;
+    Ok((name.to_string(), addr))
 }
 
 fn main() {
@@ -87,29 +266,247 @@ fn main() {
         setup_log(opt.verbose);
     }
 
-    let conf_path = opt.config.unwrap_or_else(|| "config.json".to_string());
+    let conf_path = opt
+        .config
+        .clone()
+        .unwrap_or_else(|| "config.json".to_string());
     debug!("Loading config: {}", conf_path);
-    let conf = Config::load(&conf_path).unwrap_or_default();
+    let mut conf = Config::load(&conf_path).unwrap_or_default();
+    if let Some(ref target) = opt.target {
+        conf.executable = target.clone();
+    }
 
-    info!("Opening target process: {}", conf.executable);
-    let process = memlib::from_name(&conf.executable)
+    match opt.cmd {
+        Some(Command::ListProcesses { json }) => {
+            list_processes(json);
+            return;
+        }
+        Some(Command::ConvertPattern {
+            ref pattern,
+            ref mask,
+            json,
+        }) => {
+            convert_pattern(pattern, mask.as_deref(), json);
+            return;
+        }
+        _ => {}
+    }
+
+    let source = open_source(&opt, &conf)
         .ok_or_else(|| {
-            error!("Could not open process {}!", conf.executable);
+            error!("Could not open memory source!");
             exit(1);
         })
         .unwrap();
 
-    let sigs = scan_signatures(&conf, &process);
+    match opt.cmd {
+        Some(Command::Snapshot { ref output }) => {
+            save_snapshot(&conf, source.as_ref(), output);
+            return;
+        }
+        Some(Command::ListModules { json }) => {
+            list_modules(source.as_ref(), json);
+            return;
+        }
+        Some(Command::ModuleInfo { ref module, json }) => {
+            module_info(source.as_ref(), module, json);
+            return;
+        }
+        Some(Command::ListVTables { ref module, json }) => {
+            list_vtables(source.as_ref(), module, json);
+            return;
+        }
+        _ => {}
+    }
+
+    let cache_path = opt
+        .cache
+        .clone()
+        .unwrap_or_else(|| "hazedumper.cache".to_string());
+    let mut cache = if opt.no_cache {
+        None
+    } else {
+        let mut cache = ScanCache::load(&cache_path);
+        cache.rescan = opt.rescan;
+        Some(cache)
+    };
+
+    let sigs = scan_signatures(&conf, source.as_ref(), cache.as_mut());
+    if let Some(ref mut cache) = cache {
+        if let Err(e) = cache.save(&cache_path) {
+            warn!("Could not save scan cache {}: {}", cache_path, e);
+        }
+    }
     let netvars = match conf.executable.as_ref() {
-        "csgo.exe" => scan_netvars(&sigs, &conf, &process),
+        "csgo.exe" => scan_netvars(&sigs, &conf, source.as_ref()),
         _ => None,
     };
 
-    let results = output::Results::new(sigs, netvars);
-    let filename = opt.filename.unwrap_or(conf.filename);
+    let interfaces = scan_interfaces(&conf, source.as_ref());
+
+    let results = output::Results::new(sigs, netvars, interfaces);
+    let filename = opt.filename.clone().unwrap_or(conf.filename);
     results.dump_all(&filename).expect("Dump results");
 }
 
+/// Open the `MemorySource` selected on the command line.
+fn open_source(opt: &Opt, conf: &Config) -> Option<Box<dyn MemorySource>> {
+    if let Some(ref dir) = opt.dir {
+        info!("Loading module files from: {}", dir);
+        return memlib::ImageDirectory::open(dir, &opt.bases)
+            .map(|s| Box::new(s) as Box<dyn MemorySource>);
+    }
+
+    if let Some(ref replay) = opt.replay {
+        info!("Loading snapshot: {}", replay);
+        return memlib::Archive::load(replay)
+            .map_err(|e| error!("Could not load snapshot {}: {}", replay, e))
+            .ok()
+            .map(|s| Box::new(s) as Box<dyn MemorySource>);
+    }
+
+    if let Some(ref dump) = opt.dump {
+        info!("Loading dump: {}", dump);
+        return open_dump(dump);
+    }
+
+    open_process(opt, conf)
+}
+
+/// Open a minidump or an ELF core file, depending on its magic.
+fn open_dump(path: &str) -> Option<Box<dyn MemorySource>> {
+    let mut magic = [0u8; 4];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .map_err(|e| error!("Could not read dump {}: {}", path, e))
+        .ok()?;
+
+    if &magic == b"\x7FELF" {
+        memlib::CoreDump::open(path).map(|s| Box::new(s) as Box<dyn MemorySource>)
+    } else {
+        memlib::Minidump::open(path).map(|s| Box::new(s) as Box<dyn MemorySource>)
+    }
+}
+
+/// Open the target process as a `MemorySource`.
+///
+/// With `--wait`, polls until the process exists and every module referenced
+/// by the config is loaded. Without `--timeout` there is no deadline, but the
+/// module wait gives up once the process exits.
+#[cfg(any(windows, target_os = "linux"))]
+fn open_process(opt: &Opt, conf: &Config) -> Option<Box<dyn MemorySource>> {
+    match opt.pid {
+        Some(pid) => info!("Opening target process: PID {}", pid),
+        None => info!("Opening target process: {}", conf.executable),
+    }
+
+    let deadline = opt.timeout.map(|t| Instant::now() + Duration::from_secs(t));
+    let process = loop {
+        match find_process(opt, conf) {
+            Err(memlib::ProcessError::NotFound(_)) | Err(memlib::ProcessError::OpenFailed(_))
+                if opt.wait && !is_expired(deadline) =>
+            {
+                debug!("Waiting for target process");
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => {
+                error!("{}", e);
+                return None;
+            }
+            Ok(p) => break p,
+        }
+    };
+
+    if opt.wait {
+        let names = config_modules(conf);
+        info!("Waiting for {} modules", names.len());
+        loop {
+            if !process.is_alive() {
+                error!("Target process exited while waiting for modules");
+                return None;
+            }
+            let loaded = process.modules();
+            let missing = names
+                .iter()
+                .filter(|n| !loaded.iter().any(|m| m.name.eq_ignore_ascii_case(n)))
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                break;
+            }
+            if is_expired(deadline) {
+                error!("Timed out waiting for modules: {:?}", missing);
+                return None;
+            }
+            debug!("Waiting for modules: {:?}", missing);
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    Some(Box::new(process))
+}
+
+/// Find the process selected on the command line.
+#[cfg(any(windows, target_os = "linux"))]
+fn find_process(opt: &Opt, conf: &Config) -> memlib::Result<memlib::Process> {
+    if let Some(pid) = opt.pid {
+        return memlib::open_pid(pid);
+    }
+
+    if opt.regex {
+        memlib::ProcessMatcher::regex(&conf.executable)
+    } else {
+        Ok(memlib::ProcessMatcher::Exact(conf.executable.clone()))
+    }
+    .and_then(|m| memlib::find_process(&m))
+}
+
+/// Check if the `--timeout` deadline has passed.
+#[cfg(any(windows, target_os = "linux"))]
+fn is_expired(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|d| Instant::now() >= d)
+}
+
+/// Attaching to a live process is only implemented on Windows and Linux.
+#[cfg(not(any(windows, target_os = "linux")))]
+fn open_process(_opt: &Opt, _conf: &Config) -> Option<Box<dyn MemorySource>> {
+    error!("Attaching to a process is not supported on this platform!");
+    None
+}
+
+/// Print all running processes.
+#[cfg(any(windows, target_os = "linux"))]
+fn list_processes(json: bool) {
+    let processes = memlib::processes();
+
+    if json {
+        match serde_json::to_string_pretty(&processes) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                error!("Could not serialize processes: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    println!("{:>8}  {:>6}  Name", "PID", "Bits");
+    for p in &processes {
+        let bits = match p.is_wow64 {
+            Some(true) => "32",
+            Some(false) => "64",
+            None => "?",
+        };
+        println!("{:>8}  {:>6}  {}", p.pid, bits, p.name);
+    }
+}
+
+/// Listing processes is only implemented on Windows and Linux.
+#[cfg(not(any(windows, target_os = "linux")))]
+fn list_processes(_json: bool) {
+    error!("Listing processes is not supported on this platform!");
+    exit(1);
+}
+
 /// Setup log levels for terminal and file.
 fn setup_log(v: u8) {
     use crate::LevelFilter::{Debug, Info, Trace};
@@ -136,38 +533,272 @@ fn setup_log(v: u8) {
     .unwrap();
 }
 
+/// Names of all modules the config reads from, for signatures, interfaces
+/// and netvars.
+fn config_modules(conf: &Config) -> Vec<String> {
+    conf.signatures
+        .iter()
+        .map(|s| s.module.as_str())
+        .chain(conf.interfaces.iter().map(String::as_str))
+        .chain(netvar_module(conf))
+        .map(str::to_string)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Module the netvar tables are read from, the one of `dwGetAllClasses`.
+fn netvar_module(conf: &Config) -> Option<&str> {
+    conf.signatures
+        .iter()
+        .find(|s| s.name == "dwGetAllClasses")
+        .map(|s| s.module.as_str())
+}
+
+/// Save the modules referenced by the config into a snapshot archive.
+fn save_snapshot(conf: &Config, source: &dyn MemorySource, path: &str) {
+    let names = config_modules(conf);
+    info!("Capturing snapshot of {} modules", names.len());
+
+    let archive = memlib::Archive::capture(source, &names);
+    match archive.save(path) {
+        Ok(()) => info!("Saved snapshot: {}", path),
+        Err(e) => {
+            error!("Could not save snapshot {}: {}", path, e);
+            exit(1);
+        }
+    }
+}
+
+/// Print all modules of the memory source, sorted by base address.
+fn list_modules(source: &dyn MemorySource, json: bool) {
+    let mut modules = source.modules();
+    modules.sort_by_key(|m| m.base);
+
+    if json {
+        match serde_json::to_string_pretty(&modules) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                error!("Could not serialize modules: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    let width = source.pointer_width() * 2 + 2;
+    let name_width = modules.iter().map(|m| m.name.len()).max().unwrap_or(0);
+    println!(
+        "{:<w$}  {:>10}  {:<nw$}  Path",
+        "Base",
+        "Size",
+        "Name",
+        w = width,
+        nw = name_width
+    );
+    for m in &modules {
+        println!(
+            "{:#0w$X}  {:>#10X}  {:<nw$}  {}",
+            m.base,
+            m.size,
+            m.name,
+            m.path,
+            w = width,
+            nw = name_width
+        );
+    }
+}
+
+/// Print the PE model of `name`.
+fn module_info(source: &dyn MemorySource, name: &str, json: bool) {
+    let module = source.get_module(name).unwrap_or_else(|| {
+        error!("Module {} not found", name);
+        exit(1);
+    });
+    let pe = module.pe().unwrap_or_else(|| {
+        error!("Module {} is not a PE image", name);
+        exit(1);
+    });
+
+    if json {
+        match serde_json::to_string_pretty(pe) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                error!("Could not serialize module info: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    println!("Module:        {} at {:#X}", module.name, module.base);
+    println!("TimeDateStamp: {:#010X}", pe.timestamp);
+    println!("CheckSum:      {:#010X}", pe.checksum);
+    println!("SizeOfImage:   {:#X}", pe.size_of_image);
+    if let Some(ref cv) = pe.codeview {
+        println!("PDB:           {}", cv);
+    }
+
+    println!("\nSections:");
+    for s in &pe.sections {
+        println!(
+            "  {:<8}  {:#010X}  {:#010X}  {:#010X}{}",
+            s.name,
+            s.virtual_address,
+            s.mapped_size(),
+            s.characteristics,
+            if s.is_code() { "  code" } else { "" }
+        );
+    }
+
+    println!("\nExports:");
+    for e in &pe.exports {
+        let name = e.name.as_deref().unwrap_or("<ordinal>");
+        match e.forwarder {
+            Some(ref f) => println!("  {:>5}  {} -> {}", e.ordinal, name, f),
+            None => println!("  {:>5}  {:#010X}  {}", e.ordinal, e.rva, name),
+        }
+    }
+
+    println!("\nImports:");
+    for i in &pe.imports {
+        println!("  {}", i.module);
+        for f in &i.functions {
+            match (&f.name, f.ordinal) {
+                (Some(name), _) => println!("    {:#010X}  {}", f.iat_rva, name),
+                (None, Some(o)) => println!("    {:#010X}  #{}", f.iat_rva, o),
+                (None, None) => println!("    {:#010X}  ?", f.iat_rva),
+            }
+        }
+    }
+}
+
+/// Print all RTTI vtables of `name`.
+fn list_vtables(source: &dyn MemorySource, name: &str, json: bool) {
+    let module = source.get_module(name).unwrap_or_else(|| {
+        error!("Module {} not found", name);
+        exit(1);
+    });
+    let vtables = module.vtables();
+
+    if json {
+        match serde_json::to_string_pretty(vtables) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                error!("Could not serialize vtables: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    println!("{:>10}  {:>6}  Class", "RVA", "Offset");
+    for v in vtables {
+        println!("{:#10X}  {:>#6X}  {}", v.rva, v.offset, v.class);
+    }
+}
+
+/// Print `raw` in every pattern syntax.
+fn convert_pattern(raw: &str, mask: Option<&str>, json: bool) {
+    let pattern = match mask {
+        Some(mask) => memlib::Pattern::parse_code(raw, mask),
+        None => memlib::Pattern::parse(raw),
+    }
+    .unwrap_or_else(|e| {
+        error!("Invalid pattern: {}", e);
+        exit(1);
+    });
+    let code = pattern.to_code();
+
+    if json {
+        let value = serde_json::json!({
+            "ida": pattern.to_string(),
+            "x64dbg": pattern.to_x64dbg(),
+            "code": code.map(|(pattern, mask)| serde_json::json!({
+                "pattern": pattern,
+                "mask": mask,
+            })),
+        });
+        match serde_json::to_string_pretty(&value) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                error!("Could not serialize pattern: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    println!("IDA:     {}", pattern);
+    println!("x64dbg:  {}", pattern.to_x64dbg());
+    match code {
+        Some((code, mask)) => println!("Code:    \"{}\", \"{}\"", code, mask),
+        None => println!("Code:    not representable, nibble wildcards"),
+    }
+}
+
 /// Scan the signatures from the config and return a `Map<usize>`.
-fn scan_signatures(conf: &Config, process: &memlib::Process) -> Map<usize> {
+///
+/// Signatures whose module and definition are unchanged are taken from
+/// `cache`, if given.
+fn scan_signatures(
+    conf: &Config,
+    source: &dyn MemorySource,
+    mut cache: Option<&mut ScanCache>,
+) -> Map<usize> {
     info!(
         "Starting signature scanning: {} items",
         conf.signatures.len()
     );
     let mut res = BTreeMap::new();
+    let mut cached = 0;
 
+    let mut pending = vec![];
     for sig in &conf.signatures {
-        match sigscan::find_signature(sig, process) {
+        match cache.as_deref_mut().and_then(|c| c.get(sig, source)) {
+            Some(r) => {
+                res.insert(sig.name.clone(), r);
+                info!("Cached signature: {} => {:#X}", sig.name, r);
+                cached += 1;
+            }
+            None => pending.push(sig),
+        }
+    }
+
+    let found = sigscan::find_signatures(&pending, source);
+    for (sig, r) in pending.into_iter().zip(found) {
+        match r {
             Ok(r) => {
                 res.insert(sig.name.clone(), r);
+                if let Some(ref mut cache) = cache {
+                    cache.insert(sig, source, r);
+                }
                 info!("Found signature: {} => {:#X}", sig.name, r);
             }
-            Err(err) => warn!("{} sigscan failed: {}", sig.name, err),
+            Err(err) => {
+                if let Some(ref mut cache) = cache {
+                    cache.remove(sig);
+                }
+                warn!("{} sigscan failed: {}", sig.name, err);
+            }
         };
     }
 
     info!(
-        "Finished signature scanning: {}/{} items successful",
+        "Finished signature scanning: {}/{} items successful, {} cached",
         res.len(),
-        conf.signatures.len()
+        conf.signatures.len(),
+        cached
     );
     res
 }
 
 /// Scan the netvars from the config and return a `Option<Map<i32>>`.
-fn scan_netvars(sigs: &Map<usize>, conf: &Config, process: &memlib::Process) -> Option<Map<isize>> {
+fn scan_netvars(sigs: &Map<usize>, conf: &Config, source: &dyn MemorySource) -> Option<Map<isize>> {
     info!("Starting netvar scanning: {} items", conf.netvars.len());
 
     let first = sigs.get("dwGetAllClasses")?;
-    let netvars = games::csgo::NetvarManager::new(*first, process)?;
+    let netvars = games::csgo::NetvarManager::new(Rva(*first), netvar_module(conf)?, source)?;
 
     let mut res = BTreeMap::new();
     for netvar in &conf.netvars {
@@ -187,3 +818,73 @@ fn scan_netvars(sigs: &Map<usize>, conf: &Config, process: &memlib::Process) ->
     );
     Some(res)
 }
+
+/// Walk the interface registry of every module in `conf.interfaces`.
+fn scan_interfaces(
+    conf: &Config,
+    source: &dyn MemorySource,
+) -> Option<Map<Map<output::InterfaceOffsets>>> {
+    if conf.interfaces.is_empty() {
+        return None;
+    }
+    info!(
+        "Starting interface scanning: {} modules",
+        conf.interfaces.len()
+    );
+
+    let mut res = BTreeMap::new();
+    for name in &conf.interfaces {
+        let module = match source.get_module(name) {
+            Some(module) => module,
+            None => {
+                warn!("{} interface scan failed: Module not found", name);
+                continue;
+            }
+        };
+        let iter =
+            match games::csgo::InterfaceIterator::from_module(&module, source.pointer_width()) {
+                Some(iter) => iter,
+                None => {
+                    warn!("{} interface scan failed: No interface registry", name);
+                    continue;
+                }
+            };
+
+        let mut list = BTreeMap::new();
+        for iface in iter {
+            let create_fn = match module.rva(iface.create_fn) {
+                Some(rva) => rva.0,
+                None => {
+                    warn!(
+                        "{}!{}: factory {:#X} is outside of the module",
+                        name, iface.name, iface.create_fn
+                    );
+                    continue;
+                }
+            };
+            let instance = iface.instance.and_then(|i| module.rva(i)).map(|rva| rva.0);
+            match instance {
+                Some(rva) => info!("Found interface: {}!{} => {:#X}", name, iface.name, rva),
+                None => info!(
+                    "Found interface: {}!{} => unresolved, factory {:#X}",
+                    name, iface.name, create_fn
+                ),
+            }
+            list.insert(
+                iface.name,
+                output::InterfaceOffsets {
+                    create_fn,
+                    instance,
+                },
+            );
+        }
+        res.insert(name.clone(), list);
+    }
+
+    info!(
+        "Finished interface scanning: {}/{} modules successful",
+        res.len(),
+        conf.interfaces.len()
+    );
+    Some(res)
+}
@@ -0,0 +1,401 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+extern crate chrono;
+extern crate flate2;
+
+use self::chrono::prelude::*;
+use self::flate2::read::ZlibDecoder;
+use self::flate2::write::ZlibEncoder;
+use self::flate2::Compression;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::memlib::*;
+
+/// Magic bytes at the start of every archive.
+const ARCHIVE_MAGIC: &[u8; 8] = b"HZDSNAP\0";
+
+/// Current archive format version.
+///
+/// Version 2 added the region map of every module.
+const ARCHIVE_VERSION: u32 = 2;
+
+/// Region flags in an archive.
+const REGION_READABLE: u8 = 1;
+const REGION_PROTECTION: u8 = 2;
+const REGION_READ: u8 = 4;
+const REGION_WRITE: u8 = 8;
+const REGION_EXECUTE: u8 = 16;
+
+/// Saved modules of a target, replayable as a `MemorySource`.
+///
+/// The file starts with `ARCHIVE_MAGIC` and the format version, followed by
+/// a zlib stream with the pointer width, the capture timestamp and the
+/// modules.
+#[derive(Debug)]
+pub struct Archive {
+    // Pointer width of the captured target.
+    pub pointer_width: usize,
+
+    // Time of the capture.
+    pub timestamp: DateTime<Utc>,
+
+    // Captured modules.
+    modules: BTreeMap<String, Rc<Module>>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn write_region<W: Write>(w: &mut W, r: &Region) -> io::Result<()> {
+    let mut flags = 0;
+    if r.readable {
+        flags |= REGION_READABLE;
+    }
+    if let Some(p) = r.protection {
+        flags |= REGION_PROTECTION;
+        if p.read {
+            flags |= REGION_READ;
+        }
+        if p.write {
+            flags |= REGION_WRITE;
+        }
+        if p.execute {
+            flags |= REGION_EXECUTE;
+        }
+    }
+
+    w.write_all(&(r.offset as u64).to_le_bytes())?;
+    w.write_all(&(r.size as u64).to_le_bytes())?;
+    w.write_all(&[flags])
+}
+
+fn read_region<R: Read>(r: &mut R) -> io::Result<Region> {
+    let offset = read_u64(r)? as usize;
+    let size = read_u64(r)? as usize;
+    let flags = read_bytes(r, 1)?[0];
+    let protection = if flags & REGION_PROTECTION != 0 {
+        Some(Protection {
+            read: flags & REGION_READ != 0,
+            write: flags & REGION_WRITE != 0,
+            execute: flags & REGION_EXECUTE != 0,
+        })
+    } else {
+        None
+    };
+
+    Ok(Region {
+        offset,
+        size,
+        protection,
+        readable: flags & REGION_READABLE != 0,
+    })
+}
+
+/// Check that `regions` are sorted, non-empty, do not overlap and lie
+/// within `len` bytes, as `Module::region_at` expects.
+fn valid_regions(regions: &[Region], len: usize) -> bool {
+    let mut pos = 0;
+    for r in regions {
+        match r.offset.checked_add(r.size) {
+            Some(end) if r.size > 0 && r.offset >= pos && end <= len => pos = end,
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn read_bytes<R: Read>(r: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(invalid_data("Archive truncated"));
+    }
+    Ok(buf)
+}
+
+impl Archive {
+    /// Capture the modules `names` from `source`.
+    ///
+    /// Modules that cannot be read are skipped.
+    pub fn capture<S: MemorySource + ?Sized>(source: &S, names: &[String]) -> Self {
+        let mut modules = BTreeMap::new();
+        for name in names {
+            match source.get_module(name) {
+                Some(m) => {
+                    info!("Captured module {} ({:#X} bytes)", name, m.size);
+                    modules.insert(name.clone(), m);
+                }
+                None => warn!("Could not capture module {}", name),
+            }
+        }
+
+        Archive {
+            pointer_width: source.pointer_width(),
+            timestamp: Utc::now(),
+            modules,
+        }
+    }
+
+    /// Write the archive to `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(ARCHIVE_MAGIC)?;
+        file.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+
+        let mut z = ZlibEncoder::new(file, Compression::default());
+        z.write_all(&[self.pointer_width as u8])?;
+        z.write_all(&self.timestamp.timestamp().to_le_bytes())?;
+        z.write_all(&(self.modules.len() as u32).to_le_bytes())?;
+        for module in self.modules.values() {
+            z.write_all(&(module.name.len() as u32).to_le_bytes())?;
+            z.write_all(module.name.as_bytes())?;
+            z.write_all(&(module.base.0 as u64).to_le_bytes())?;
+            z.write_all(&(module.size as u64).to_le_bytes())?;
+            z.write_all(&(module.data.len() as u64).to_le_bytes())?;
+            z.write_all(&module.data)?;
+            z.write_all(&(module.regions.len() as u32).to_le_bytes())?;
+            for r in &module.regions {
+                write_region(&mut z, r)?;
+            }
+        }
+        z.finish()?.flush()
+    }
+
+    /// Read an archive from `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(invalid_data("Not a hazedumper snapshot"));
+        }
+
+        let version = read_u32(&mut file)?;
+        if version == 0 || version > ARCHIVE_VERSION {
+            return Err(invalid_data(&format!(
+                "Unsupported snapshot version {}",
+                version
+            )));
+        }
+
+        let mut z = ZlibDecoder::new(file);
+        let pointer_width = read_bytes(&mut z, 1)?[0] as usize;
+        let timestamp = read_u64(&mut z)? as i64;
+        let timestamp = Utc
+            .timestamp_opt(timestamp, 0)
+            .single()
+            .ok_or_else(|| invalid_data("Invalid snapshot timestamp"))?;
+
+        let mut modules = BTreeMap::new();
+        for _ in 0..read_u32(&mut z)? {
+            let len = read_u32(&mut z)? as usize;
+            let name = String::from_utf8(read_bytes(&mut z, len)?)
+                .map_err(|_| invalid_data("Invalid module name"))?;
+            let base = Va(read_u64(&mut z)? as usize);
+            let size = read_u64(&mut z)? as usize;
+            let len = read_u64(&mut z)? as usize;
+            let data = read_bytes(&mut z, len)?;
+            let regions = if version >= 2 {
+                let count = read_u32(&mut z)?;
+                (0..count)
+                    .map(|_| read_region(&mut z))
+                    .collect::<io::Result<Vec<_>>>()?
+            } else {
+                vec![Region::whole(data.len())]
+            };
+            if !valid_regions(&regions, data.len()) {
+                return Err(invalid_data("Invalid module region"));
+            }
+
+            debug!("Loaded module {} at {:#X} size {:#X}", name, base, size);
+            if size != data.len() {
+                return Err(invalid_data("Invalid module size"));
+            }
+            let module = Module::with_regions(&name, base, data, regions);
+            modules.insert(name, Rc::new(module));
+        }
+
+        Ok(Archive {
+            pointer_width,
+            timestamp,
+            modules,
+        })
+    }
+}
+
+impl MemorySource for Archive {
+    fn modules(&self) -> Vec<ModuleInfo> {
+        self.modules
+            .values()
+            .map(|m| ModuleInfo {
+                name: m.name.clone(),
+                path: String::new(),
+                base: m.base,
+                size: m.size,
+            })
+            .collect()
+    }
+
+    fn get_module(&self, name: &str) -> Option<Rc<Module>> {
+        self.modules.get(name).cloned().or_else(|| {
+            self.modules
+                .values()
+                .find(|m| m.name.eq_ignore_ascii_case(name))
+                .cloned()
+        })
+    }
+
+    fn read_bytes(&self, address: Va, buf: &mut [u8]) -> bool {
+        self.modules
+            .values()
+            .any(|m| m.read_into(address, buf).is_ok())
+    }
+
+    fn pointer_width(&self) -> usize {
+        self.pointer_width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const BASE: Va = Va(0x1000_0000);
+
+    /// A file in the temp directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            TempFile(env::temp_dir().join(format!("{}-{}.hzs", name, std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn archive(regions: Vec<Region>) -> Archive {
+        let data = (0..0x3000).map(|i| i as u8).collect::<Vec<_>>();
+        let module = Module::with_regions("test.dll", BASE, data, regions);
+        let mut modules = BTreeMap::new();
+        modules.insert(module.name.clone(), Rc::new(module));
+        Archive {
+            pointer_width: 4,
+            timestamp: Utc.timestamp_opt(1_500_000_000, 0).unwrap(),
+            modules,
+        }
+    }
+
+    #[test]
+    fn round_trips_regions() {
+        let rw = Protection {
+            read: true,
+            write: true,
+            execute: false,
+        };
+        let mut regions = Region::from_ranges(0x3000, vec![(0, 0x1000), (0x2000, 0x3000)]);
+        regions[2].protection = Some(rw);
+
+        let file = TempFile::new("round_trips_regions");
+        archive(regions.clone()).save(&file.0).unwrap();
+        let loaded = Archive::load(&file.0).unwrap();
+
+        assert_eq!(loaded.pointer_width, 4);
+        assert_eq!(loaded.timestamp.timestamp(), 1_500_000_000);
+        let module = loaded.get_module("TEST.DLL").unwrap();
+        assert_eq!(module.base, BASE);
+        assert_eq!(module.regions, regions);
+        assert_eq!(module.data[0x2345], 0x45);
+        assert_eq!(loaded.read::<u8>(BASE + 0x10), Ok(0x10));
+        assert!(loaded.read::<u8>(BASE + 0x1010).is_err());
+    }
+
+    #[test]
+    fn loads_version_1() {
+        let file = TempFile::new("loads_version_1");
+        let mut out = File::create(&file.0).unwrap();
+        out.write_all(ARCHIVE_MAGIC).unwrap();
+        out.write_all(&1u32.to_le_bytes()).unwrap();
+        let mut z = ZlibEncoder::new(out, Compression::default());
+        z.write_all(&[8]).unwrap();
+        z.write_all(&1_500_000_000i64.to_le_bytes()).unwrap();
+        z.write_all(&1u32.to_le_bytes()).unwrap();
+        z.write_all(&8u32.to_le_bytes()).unwrap();
+        z.write_all(b"test.dll").unwrap();
+        z.write_all(&(BASE.0 as u64).to_le_bytes()).unwrap();
+        z.write_all(&4u64.to_le_bytes()).unwrap();
+        z.write_all(&4u64.to_le_bytes()).unwrap();
+        z.write_all(&[1, 2, 3, 4]).unwrap();
+        z.finish().unwrap();
+
+        let loaded = Archive::load(&file.0).unwrap();
+        assert_eq!(loaded.pointer_width, 8);
+        let module = loaded.get_module("test.dll").unwrap();
+        assert_eq!(module.regions, vec![Region::whole(4)]);
+        assert_eq!(loaded.read::<u32>(BASE), Ok(0x0403_0201));
+    }
+
+    #[test]
+    fn rejects_invalid_regions() {
+        let region = |offset, size| Region {
+            offset,
+            size,
+            protection: None,
+            readable: true,
+        };
+        let invalid = vec![
+            vec![region(0x1000, usize::MAX)],
+            vec![region(0, 0x2000), region(0x1000, 0x2000)],
+            vec![region(0x2000, 0x1000), region(0, 0x1000)],
+            vec![region(0, 0x4000)],
+        ];
+        for (i, regions) in invalid.into_iter().enumerate() {
+            let file = TempFile::new(&format!("rejects_invalid_regions{}", i));
+            archive(regions).save(&file.0).unwrap();
+            assert!(Archive::load(&file.0).is_err());
+        }
+    }
+}
@@ -0,0 +1,321 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+#![allow(clippy::double_parens)]
+
+use nom::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::helpers::file_name;
+use crate::memlib::*;
+
+/// `ET_CORE`
+const ET_CORE: u16 = 4;
+
+/// `PT_NOTE`
+const PT_NOTE: u32 = 4;
+
+/// `NT_FILE`
+const NT_FILE: u32 = 0x4649_4C45;
+
+/// A file mapping listed in the `NT_FILE` note.
+#[derive(Debug, Clone, PartialEq)]
+struct FileMapping {
+    start: usize,
+    end: usize,
+    offset: u64,
+    path: String,
+}
+
+// namesz, descsz, type
+#[rustfmt::skip]
+named!(
+    parse_note_header<(usize, usize, u32)>,
+    do_parse!(
+        namesz : le_u32 >>
+        descsz : le_u32 >>
+        kind   : le_u32 >>
+        ((namesz as usize, descsz as usize, kind))
+    )
+);
+
+/// Round `value` up to a multiple of 4, the note alignment.
+fn align4(value: usize) -> usize {
+    value.saturating_add(3) & !3
+}
+
+/// Parse the description of an `NT_FILE` note.
+fn parse_nt_file(desc: &[u8], width: usize) -> Option<Vec<FileMapping>> {
+    let count = read_word(desc, 0, width)? as usize;
+    let page_size = read_word(desc, width, width)?;
+
+    let mut entries = vec![];
+    let mut pos = 2 * width;
+    for _ in 0..count {
+        let start = read_word(desc, pos, width)? as usize;
+        let end = read_word(desc, pos + width, width)? as usize;
+        let offset = read_word(desc, pos + 2 * width, width)?.checked_mul(page_size)?;
+        entries.push((start, end, offset));
+        pos += 3 * width;
+    }
+
+    let paths = desc.get(pos..)?.split(|&c| c == 0);
+    Some(
+        entries
+            .into_iter()
+            .zip(paths)
+            .map(|((start, end, offset), path)| FileMapping {
+                start,
+                end,
+                offset,
+                path: String::from_utf8_lossy(path).into_owned(),
+            })
+            .collect(),
+    )
+}
+
+/// An ELF core dump of a crashed Linux process.
+///
+/// Pages the core omits are taken from the mapped files if they are still on
+/// disk, for `Module`s and `read_bytes` alike. Those bytes reflect the files
+/// as they are now, not as they were mapped when the process crashed.
+#[derive(Debug)]
+pub struct CoreDump {
+    // Memory captured in the core.
+    memory: DumpMemory,
+
+    // File mappings from the `NT_FILE` note.
+    files: Vec<FileMapping>,
+
+    // Modules of the crashed process.
+    module_list: Vec<ModuleInfo>,
+
+    // Pointer width of the crashed process.
+    pointer_width: usize,
+
+    // Modules read from the core.
+    modules: RefCell<HashMap<String, Rc<Module>>>,
+
+    // Contents of the mapped files, `None` if a file could not be read.
+    file_data: RefCell<HashMap<String, Option<Rc<Vec<u8>>>>>,
+}
+
+impl CoreDump {
+    /// Open an ELF core file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let mut file = File::open(path.as_ref())
+            .map_err(|e| warn!("Could not open {}: {}", path.as_ref().display(), e))
+            .ok()?;
+
+        let raw = read_file_at(&mut file, 0, 64)?;
+        let size = match ElfHeaders::headers_size(&raw) {
+            Some(size) => size,
+            None => {
+                warn!("Not an ELF file: {}", path.as_ref().display());
+                return None;
+            }
+        };
+        let raw = read_file_at(&mut file, 0, size)?;
+        let headers = ElfHeaders::parse(&raw)?;
+        if headers.elf_type != ET_CORE {
+            warn!("Not a core file: {}", path.as_ref().display());
+            return None;
+        }
+
+        let ranges = headers
+            .segments()
+            .map(|p| FileRange {
+                address: p.vaddr,
+                size: p.filesz,
+                offset: p.offset as u64,
+            })
+            .collect::<Vec<_>>();
+
+        let mut files = vec![];
+        for note in headers.program_headers.iter().filter(|p| p.kind == PT_NOTE) {
+            let data = read_file_at(&mut file, note.offset as u64, note.filesz)?;
+            let mut pos = 0;
+            while let Ok((_, (namesz, descsz, kind))) = parse_note_header(&data[pos..]) {
+                let desc = match align4(namesz).checked_add(pos + 12) {
+                    Some(desc) if desc <= data.len() => desc,
+                    _ => break,
+                };
+                if kind == NT_FILE {
+                    if let Some(desc) = data.get(desc..desc.saturating_add(descsz)) {
+                        let width = headers.pointer_width();
+                        files.extend(parse_nt_file(desc, width).unwrap_or_default());
+                    }
+                }
+                pos = desc.saturating_add(align4(descsz));
+                if pos >= data.len() {
+                    break;
+                }
+            }
+        }
+        debug!(
+            "Core dump: {} memory ranges, {} file mappings",
+            ranges.len(),
+            files.len()
+        );
+
+        let mut core = CoreDump {
+            memory: DumpMemory::new(file, ranges),
+            files,
+            module_list: vec![],
+            pointer_width: headers.pointer_width(),
+            modules: RefCell::new(HashMap::new()),
+            file_data: RefCell::new(HashMap::new()),
+        };
+        core.module_list = core.find_modules();
+        Some(core)
+    }
+
+    /// Group the file mappings into modules.
+    ///
+    /// Only files that look like executable images are reported.
+    fn find_modules(&self) -> Vec<ModuleInfo> {
+        let mut res: Vec<ModuleInfo> = vec![];
+        for m in self.files.iter().filter(|m| m.offset == 0) {
+            let name = file_name(&m.path).to_string();
+            if res.iter().any(|i| i.name == name) {
+                continue;
+            }
+
+            let mut header = vec![0u8; 0x1000.min(m.end.saturating_sub(m.start))];
+            self.memory.read_sparse(Va(m.start), &mut header);
+            if ImageHeaders::parse(&header).is_none() && !is_image_name(&name) {
+                continue;
+            }
+
+            let end = self
+                .files
+                .iter()
+                .filter(|f| f.path == m.path && f.start >= m.start)
+                .map(|f| f.end)
+                .max()
+                .unwrap_or(m.end)
+                .max(m.start);
+            debug!(
+                "Core module {} at {:#X} size {:#X}",
+                name,
+                m.start,
+                end - m.start
+            );
+            res.push(ModuleInfo {
+                name,
+                path: m.path.clone(),
+                base: Va(m.start),
+                size: end - m.start,
+            });
+        }
+        res
+    }
+
+    /// Contents of the mapped file at `path`, read on first use.
+    fn file(&self, path: &str) -> Option<Rc<Vec<u8>>> {
+        self.file_data
+            .borrow_mut()
+            .entry(path.to_string())
+            .or_insert_with(|| fs::read(path).ok().map(Rc::new))
+            .clone()
+    }
+
+    /// Fill `data` at `base` from the mapped files that are still on disk.
+    ///
+    /// Cores usually omit unmodified file backed pages such as code.
+    /// Returns the filled ranges, relative to `base`.
+    fn read_from_files(&self, base: Va, data: &mut [u8]) -> Vec<(usize, usize)> {
+        let base = base.0;
+        let mut res = vec![];
+        let end = base.saturating_add(data.len());
+        for m in self.files.iter().filter(|m| m.start < end && m.end > base) {
+            let file = match self.file(&m.path) {
+                Some(file) => file,
+                None => continue,
+            };
+
+            let start = m.start.max(base);
+            let stop = m.end.min(end);
+            let offset = match (m.offset as usize).checked_add(start - m.start) {
+                Some(offset) => offset,
+                None => continue,
+            };
+            if let Some(src) = file.get(offset..offset.saturating_add(stop - start).min(file.len()))
+            {
+                data[start - base..start - base + src.len()].copy_from_slice(src);
+                res.push((start - base, start - base + src.len()));
+            }
+        }
+        res
+    }
+}
+
+impl MemorySource for CoreDump {
+    fn modules(&self) -> Vec<ModuleInfo> {
+        self.module_list.clone()
+    }
+
+    fn get_module(&self, name: &str) -> Option<Rc<Module>> {
+        let mut b = self.modules.borrow_mut();
+        if b.contains_key(name) {
+            return b.get(name).cloned();
+        }
+
+        let info = self
+            .module_list
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(name))?;
+        let mut data = vec![0u8; info.size];
+        let mut present = self.read_from_files(info.base, &mut data);
+        if self.memory.read_sparse(info.base, &mut data) == 0 {
+            debug!("Module {} is not contained in the core", info.name);
+        }
+        present.extend(self.memory.present(info.base, info.size));
+
+        let regions = Region::from_ranges(info.size, present);
+        let module = Module::with_regions(&info.name, info.base, data, regions);
+        b.insert(name.to_string(), Rc::new(module));
+        b.get(name).cloned()
+    }
+
+    fn read_bytes(&self, address: Va, buf: &mut [u8]) -> bool {
+        if self.memory.read(address, buf) {
+            return true;
+        }
+
+        // Same as for modules, the core takes precedence over the files.
+        let mut present = self.read_from_files(address, buf);
+        self.memory.read_sparse(address, buf);
+        present.extend(self.memory.present(address, buf.len()));
+        Region::from_ranges(buf.len(), present)
+            .iter()
+            .all(|r| r.readable)
+    }
+
+    fn pointer_width(&self) -> usize {
+        self.pointer_width
+    }
+}
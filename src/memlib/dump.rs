@@ -0,0 +1,126 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::cell::RefCell;
+use std::cmp;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::memlib::Va;
+
+/// A range of target memory stored in a dump file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileRange {
+    // Target address of the first byte.
+    pub address: usize,
+
+    // Size in bytes.
+    pub size: usize,
+
+    // File offset of the first byte.
+    pub offset: u64,
+}
+
+/// The address space of a dumped process, read from the dump file on demand.
+#[derive(Debug)]
+pub struct DumpMemory {
+    file: RefCell<File>,
+    ranges: Vec<FileRange>,
+}
+
+/// Read `len` bytes at `offset` from `file`.
+///
+/// Offsets and lengths usually come from the file itself, so ranges past its
+/// end are rejected before allocating the buffer.
+pub fn read_file_at(file: &mut File, offset: u64, len: usize) -> Option<Vec<u8>> {
+    let size = file.metadata().ok()?.len();
+    if offset.checked_add(len as u64)? > size {
+        return None;
+    }
+    let mut buf = vec![0u8; len];
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.read_exact(&mut buf).ok()?;
+    Some(buf)
+}
+
+impl DumpMemory {
+    pub fn new(file: File, mut ranges: Vec<FileRange>) -> Self {
+        ranges.retain(|r| r.size != 0);
+        ranges.sort_by_key(|r| r.address);
+        DumpMemory {
+            file: RefCell::new(file),
+            ranges,
+        }
+    }
+
+    /// Read into `buf`, leaving bytes not present in the dump untouched.
+    ///
+    /// Returns the number of bytes that were present.
+    pub fn read_sparse(&self, address: Va, buf: &mut [u8]) -> usize {
+        let address = address.0;
+        let end = address.saturating_add(buf.len());
+        let first = self
+            .ranges
+            .partition_point(|r| r.address.saturating_add(r.size) <= address);
+
+        let mut file = self.file.borrow_mut();
+        let mut found = 0;
+        for r in self.ranges[first..].iter().take_while(|r| r.address < end) {
+            let start = cmp::max(address, r.address);
+            let stop = cmp::min(end, r.address.saturating_add(r.size));
+            if start >= stop {
+                continue;
+            }
+
+            let offset = r.offset.saturating_add((start - r.address) as u64);
+            let dst = &mut buf[start - address..stop - address];
+            if file.seek(SeekFrom::Start(offset)).is_err() || file.read_exact(dst).is_err() {
+                warn!("Dump file truncated at offset {:#X}", offset);
+                continue;
+            }
+            found += stop - start;
+        }
+
+        found
+    }
+
+    /// Ranges of `size` bytes at `address` present in the dump, relative to
+    /// `address`.
+    pub fn present(&self, address: Va, size: usize) -> Vec<(usize, usize)> {
+        let address = address.0;
+        let end = address.saturating_add(size);
+        self.ranges
+            .iter()
+            .filter(|r| r.address < end && r.address.saturating_add(r.size) > address)
+            .map(|r| {
+                let start = cmp::max(address, r.address);
+                let stop = cmp::min(end, r.address.saturating_add(r.size));
+                (start - address, stop - address)
+            })
+            .collect()
+    }
+
+    /// Read into `buf`, failing if any byte is not present in the dump.
+    pub fn read(&self, address: Va, buf: &mut [u8]) -> bool {
+        self.read_sparse(address, buf) == buf.len()
+    }
+}
@@ -0,0 +1,655 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+#![allow(clippy::double_parens)]
+
+use nom::*;
+use std::cmp;
+
+use crate::memlib::{read_word, Module, Va};
+
+/// `ELFCLASS32`
+const ELFCLASS32: u8 = 1;
+
+/// `ELFCLASS64`
+const ELFCLASS64: u8 = 2;
+
+/// `ELFDATA2LSB`
+const ELFDATA2LSB: u8 = 1;
+
+/// `PT_LOAD`
+pub const PT_LOAD: u32 = 1;
+
+/// `PT_DYNAMIC`
+pub const PT_DYNAMIC: u32 = 2;
+
+/// `PF_W`
+pub const PF_W: u32 = 2;
+
+/// `DT_NULL`
+const DT_NULL: u64 = 0;
+
+/// `DT_RELA`
+const DT_RELA: u64 = 7;
+
+/// `DT_RELASZ`
+const DT_RELASZ: u64 = 8;
+
+/// `DT_REL`
+const DT_REL: u64 = 17;
+
+/// `DT_RELSZ`
+const DT_RELSZ: u64 = 18;
+
+/// `DT_RELRSZ`
+const DT_RELRSZ: u64 = 35;
+
+/// `DT_RELR`
+const DT_RELR: u64 = 36;
+
+/// `R_386_RELATIVE` and `R_X86_64_RELATIVE`
+const R_RELATIVE: u64 = 8;
+
+/// Segments are mapped with page granularity.
+const PAGE_SIZE: usize = 0x1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: usize,
+    pub vaddr: usize,
+    pub filesz: usize,
+    pub memsz: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileHeader {
+    is_64bit: bool,
+    elf_type: u16,
+    machine: u16,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+}
+
+/// The parts of the ELF headers needed to map and inspect an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElfHeaders {
+    pub is_64bit: bool,
+    pub elf_type: u16,
+    pub machine: u16,
+    pub program_headers: Vec<ProgramHeader>,
+}
+
+// class
+#[rustfmt::skip]
+named!(
+    parse_ident<u8>,
+    do_parse!(
+        tag!(b"\x7FELF")                            >>
+        class : le_u8                               >>
+        verify!(le_u8, |d| d == ELFDATA2LSB)        >>
+        take!(10)                                   >>
+        (class)
+    )
+);
+
+// e_type, e_machine, e_phoff, e_phentsize, e_phnum
+#[rustfmt::skip]
+named!(
+    parse_header32<(u16, u16, usize, usize, usize)>,
+    do_parse!(
+        elf_type  : le_u16 >>
+        machine   : le_u16 >>
+        take!(8)           >>
+        phoff     : le_u32 >>
+        take!(10)          >>
+        phentsize : le_u16 >>
+        phnum     : le_u16 >>
+        ((elf_type, machine, phoff as usize, phentsize as usize, phnum as usize))
+    )
+);
+
+// e_type, e_machine, e_phoff, e_phentsize, e_phnum
+#[rustfmt::skip]
+named!(
+    parse_header64<(u16, u16, usize, usize, usize)>,
+    do_parse!(
+        elf_type  : le_u16 >>
+        machine   : le_u16 >>
+        take!(12)          >>
+        phoff     : le_u64 >>
+        take!(14)          >>
+        phentsize : le_u16 >>
+        phnum     : le_u16 >>
+        ((elf_type, machine, phoff as usize, phentsize as usize, phnum as usize))
+    )
+);
+
+#[rustfmt::skip]
+named!(
+    parse_program_header32<ProgramHeader>,
+    do_parse!(
+        kind   : le_u32 >>
+        offset : le_u32 >>
+        vaddr  : le_u32 >>
+        take!(4)        >>
+        filesz : le_u32 >>
+        memsz  : le_u32 >>
+        flags  : le_u32 >>
+        (ProgramHeader {
+            kind,
+            flags,
+            offset: offset as usize,
+            vaddr: vaddr as usize,
+            filesz: filesz as usize,
+            memsz: memsz as usize,
+        })
+    )
+);
+
+#[rustfmt::skip]
+named!(
+    parse_program_header64<ProgramHeader>,
+    do_parse!(
+        kind   : le_u32 >>
+        flags  : le_u32 >>
+        offset : le_u64 >>
+        vaddr  : le_u64 >>
+        take!(8)        >>
+        filesz : le_u64 >>
+        memsz  : le_u64 >>
+        (ProgramHeader {
+            kind,
+            flags,
+            offset: offset as usize,
+            vaddr: vaddr as usize,
+            filesz: filesz as usize,
+            memsz: memsz as usize,
+        })
+    )
+);
+
+/// Write a little endian word of `width` bytes.
+fn write_word(data: &mut [u8], pos: usize, width: usize, value: u64) {
+    let end = match pos.checked_add(width) {
+        Some(end) => end,
+        None => return,
+    };
+    if let Some(raw) = data.get_mut(pos..end) {
+        raw.copy_from_slice(&value.to_le_bytes()[..width]);
+    }
+}
+
+/// Offset of entry `index` of a table at `start`, if the whole entry is
+/// addressable.
+fn table_entry(start: usize, index: usize, entry: usize) -> Option<usize> {
+    let pos = index.checked_mul(entry)?.checked_add(start)?;
+    pos.checked_add(entry)?;
+    Some(pos)
+}
+
+impl ElfHeaders {
+    /// Parse the file header.
+    fn parse_file_header(data: &[u8]) -> Option<FileHeader> {
+        let (rest, class) = parse_ident(data).ok()?;
+        let is_64bit = match class {
+            ELFCLASS32 => false,
+            ELFCLASS64 => true,
+            _ => return None,
+        };
+
+        let (_, (elf_type, machine, phoff, phentsize, phnum)) = if is_64bit {
+            parse_header64(rest).ok()?
+        } else {
+            parse_header32(rest).ok()?
+        };
+        Some(FileHeader {
+            is_64bit,
+            elf_type,
+            machine,
+            phoff,
+            phentsize,
+            phnum,
+        })
+    }
+
+    /// Number of bytes from the start of the file needed by `parse`.
+    ///
+    /// Only needs the 64 byte file header. `None` if the program headers do
+    /// not fit the address space.
+    pub fn headers_size(data: &[u8]) -> Option<usize> {
+        let h = ElfHeaders::parse_file_header(data)?;
+        h.phentsize.checked_mul(h.phnum)?.checked_add(h.phoff)
+    }
+
+    /// Parse the headers of an ELF file or of a mapped ELF image.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let FileHeader {
+            is_64bit,
+            elf_type,
+            machine,
+            phoff,
+            phentsize,
+            phnum,
+        } = ElfHeaders::parse_file_header(data)?;
+
+        let mut program_headers = vec![];
+        for i in 0..phnum {
+            let raw = data.get(table_entry(phoff, i, phentsize)?..)?;
+            let (_, ph) = if is_64bit {
+                parse_program_header64(raw).ok()?
+            } else {
+                parse_program_header32(raw).ok()?
+            };
+            program_headers.push(ph);
+        }
+
+        Some(ElfHeaders {
+            is_64bit,
+            elf_type,
+            machine,
+            program_headers,
+        })
+    }
+
+    /// Pointer width of the image in bytes.
+    pub fn pointer_width(&self) -> usize {
+        if self.is_64bit {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// All `PT_LOAD` segments.
+    pub fn segments(&self) -> impl Iterator<Item = &ProgramHeader> {
+        self.program_headers.iter().filter(|p| p.kind == PT_LOAD)
+    }
+
+    /// Page aligned virtual address of the first segment.
+    pub fn min_vaddr(&self) -> usize {
+        self.segments().map(|p| p.vaddr).min().unwrap_or(0) & !(PAGE_SIZE - 1)
+    }
+
+    /// Size of the address range covered by all segments.
+    pub fn size_of_image(&self) -> usize {
+        let end = self
+            .segments()
+            .map(|p| p.vaddr + p.memsz)
+            .max()
+            .unwrap_or(0);
+        (end - self.min_vaddr()).div_ceil(PAGE_SIZE) * PAGE_SIZE
+    }
+
+    /// Lay out the segments of an ELF file by virtual address.
+    pub fn map(&self, file: &[u8]) -> Vec<u8> {
+        let min_vaddr = self.min_vaddr();
+        let mut image = vec![0u8; self.size_of_image()];
+
+        for seg in self.segments() {
+            let start = seg.vaddr - min_vaddr;
+            let len = cmp::min(seg.filesz, seg.memsz);
+            let len = cmp::min(len, file.len().saturating_sub(seg.offset));
+            let len = cmp::min(len, image.len().saturating_sub(start));
+            if len == 0 {
+                continue;
+            }
+
+            trace!("Mapping segment ({:#X} bytes) to {:#X}", len, seg.vaddr);
+            image[start..start + len].copy_from_slice(&file[seg.offset..seg.offset + len]);
+        }
+
+        image
+    }
+
+    /// Apply the `RELATIVE` relocations of a mapped image for a new `base`.
+    ///
+    /// Other relocation types need symbol resolution and are left alone.
+    pub fn relocate(&self, image: &mut [u8], base: usize) {
+        let min_vaddr = self.min_vaddr();
+        let bias = (base as u64).wrapping_sub(min_vaddr as u64);
+        let width = self.pointer_width();
+
+        let dynamic = match self.program_headers.iter().find(|p| p.kind == PT_DYNAMIC) {
+            Some(d) => d,
+            None => return,
+        };
+
+        let (mut rel, mut relsz, mut rela, mut relasz, mut relr, mut relrsz) = (0, 0, 0, 0, 0, 0);
+        let start = match dynamic.vaddr.checked_sub(min_vaddr) {
+            Some(start) => start,
+            None => {
+                warn!("PT_DYNAMIC below the first segment, skipping relocations");
+                return;
+            }
+        };
+        let mut index = 0;
+        while let Some(pos) = table_entry(start, index, 2 * width) {
+            let (tag, val) = match (
+                read_word(image, pos, width),
+                read_word(image, pos + width, width),
+            ) {
+                (Some(tag), Some(val)) => (tag, val),
+                _ => break,
+            };
+            match tag {
+                DT_NULL => break,
+                DT_REL => rel = val as usize,
+                DT_RELSZ => relsz = val as usize,
+                DT_RELA => rela = val as usize,
+                DT_RELASZ => relasz = val as usize,
+                DT_RELR => relr = val as usize,
+                DT_RELRSZ => relrsz = val as usize,
+                _ => {}
+            }
+            index += 1;
+        }
+
+        // Elf32_Rel: r_offset, r_info. The addend is stored in place.
+        let entry = 2 * width;
+        for i in 0..relsz / entry {
+            let pos = match table_entry(rel.wrapping_sub(min_vaddr), i, entry) {
+                Some(pos) => pos,
+                None => {
+                    warn!("Malformed DT_REL table, skipping it");
+                    break;
+                }
+            };
+            let (offset, info) = match (
+                read_word(image, pos, width),
+                read_word(image, pos + width, width),
+            ) {
+                (Some(o), Some(i)) => (o as usize, i),
+                _ => break,
+            };
+            if info & 0xFF == R_RELATIVE && bias != 0 {
+                let target = offset.wrapping_sub(min_vaddr);
+                if let Some(value) = read_word(image, target, width) {
+                    write_word(image, target, width, value.wrapping_add(bias));
+                }
+            }
+        }
+
+        // Elf64_Rela: r_offset, r_info, r_addend.
+        let entry = 3 * width;
+        for i in 0..relasz / entry {
+            let pos = match table_entry(rela.wrapping_sub(min_vaddr), i, entry) {
+                Some(pos) => pos,
+                None => {
+                    warn!("Malformed DT_RELA table, skipping it");
+                    break;
+                }
+            };
+            let (offset, info, addend) = match (
+                read_word(image, pos, width),
+                read_word(image, pos + width, width),
+                read_word(image, pos + 2 * width, width),
+            ) {
+                (Some(o), Some(i), Some(a)) => (o as usize, i, a),
+                _ => break,
+            };
+            let kind = if self.is_64bit {
+                info & 0xFFFF_FFFF
+            } else {
+                info & 0xFF
+            };
+            if kind == R_RELATIVE {
+                let target = offset.wrapping_sub(min_vaddr);
+                write_word(image, target, width, addend.wrapping_add(bias));
+            }
+        }
+
+        // Packed relative relocations: an address, followed by bitmaps of
+        // the words after it.
+        let mut next = 0usize;
+        for i in 0..relrsz / width {
+            let entry = match table_entry(relr.wrapping_sub(min_vaddr), i, width)
+                .and_then(|pos| read_word(image, pos, width))
+            {
+                Some(e) => e,
+                None => break,
+            };
+
+            let bits = 8 * width - 1;
+            let targets = if entry & 1 == 0 {
+                next = (entry as usize).wrapping_sub(min_vaddr);
+                next.checked_add(width).map(|n| (vec![next], n))
+            } else {
+                next.checked_add(bits * width).map(|n| {
+                    let targets = (0..bits)
+                        .filter(|b| (entry >> (b + 1)) & 1 == 1)
+                        .map(|b| next + b * width)
+                        .collect();
+                    (targets, n)
+                })
+            };
+            let targets = match targets {
+                Some((targets, n)) => {
+                    next = n;
+                    targets
+                }
+                None => {
+                    warn!("Malformed DT_RELR table, skipping it");
+                    break;
+                }
+            };
+
+            if bias == 0 {
+                continue;
+            }
+            for target in targets {
+                if let Some(value) = read_word(image, target, width) {
+                    write_word(image, target, width, value.wrapping_add(bias));
+                }
+            }
+        }
+    }
+}
+
+impl Module {
+    /// Map an ELF file as if the dynamic loader had placed it at `base`.
+    ///
+    /// Uses the address of the first segment if no `base` is given.
+    pub fn from_elf_file(name: &str, file: &[u8], base: Option<usize>) -> Option<Self> {
+        let headers = ElfHeaders::parse(file)?;
+        let base = base.unwrap_or_else(|| headers.min_vaddr());
+        debug!(
+            "Mapping ELF image {}: size {:#X} => base {:#X}",
+            name,
+            headers.size_of_image(),
+            base
+        );
+
+        let mut data = headers.map(file);
+        headers.relocate(&mut data, base);
+
+        Some(Module::new(name, Va(base), data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_VADDR: usize = 0x10000;
+
+    fn segment(
+        kind: u32,
+        offset: usize,
+        vaddr: usize,
+        filesz: usize,
+        memsz: usize,
+    ) -> ProgramHeader {
+        ProgramHeader {
+            kind,
+            flags: 5,
+            offset,
+            vaddr,
+            filesz,
+            memsz,
+        }
+    }
+
+    /// Headers of a one page image with its dynamic section at offset 0x100.
+    fn headers(is_64bit: bool) -> ElfHeaders {
+        ElfHeaders {
+            is_64bit,
+            elf_type: 3,
+            machine: 0,
+            program_headers: vec![
+                segment(PT_LOAD, 0, MIN_VADDR, 0x1000, 0x1000),
+                segment(PT_DYNAMIC, 0x100, MIN_VADDR + 0x100, 0x100, 0x100),
+            ],
+        }
+    }
+
+    /// A mapped image with the `dynamic` entries and `words` at their offsets.
+    fn image(width: usize, dynamic: &[(u64, u64)], words: &[(usize, u64)]) -> Vec<u8> {
+        let mut image = vec![0u8; 0x1000];
+        for (i, &(tag, val)) in dynamic.iter().enumerate() {
+            write_word(&mut image, 0x100 + 2 * width * i, width, tag);
+            write_word(&mut image, 0x100 + 2 * width * i + width, width, val);
+        }
+        for &(pos, value) in words {
+            write_word(&mut image, pos, width, value);
+        }
+        image
+    }
+
+    fn words(image: &[u8], width: usize, pos: &[usize]) -> Vec<u64> {
+        pos.iter()
+            .map(|&p| read_word(image, p, width).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn maps_segments() {
+        let file = (0..0x30).collect::<Vec<u8>>();
+        let headers = ElfHeaders {
+            program_headers: vec![
+                segment(PT_LOAD, 0, MIN_VADDR, 0x10, 0x10),
+                segment(PT_LOAD, 0x10, MIN_VADDR + 0x2008, 0x20, 0x40),
+                // Truncated by the end of the file.
+                segment(PT_LOAD, 0x28, MIN_VADDR + 0x1000, 0x100, 0x100),
+                // Entirely past the end of the file.
+                segment(PT_LOAD, 0x1000, MIN_VADDR + 0x1800, 0x10, 0x10),
+            ],
+            ..headers(false)
+        };
+        assert_eq!(headers.min_vaddr(), MIN_VADDR);
+        assert_eq!(headers.size_of_image(), 0x3000);
+
+        let image = headers.map(&file);
+        assert_eq!(image.len(), 0x3000);
+        assert_eq!(&image[..0x10], &file[..0x10]);
+        assert_eq!(&image[0x2008..0x2028], &file[0x10..0x30]);
+        assert!(image[0x2028..0x2048].iter().all(|&b| b == 0));
+        assert_eq!(&image[0x1000..0x1008], &file[0x28..0x30]);
+        assert!(image[0x1008..0x2008].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn relocates_rel() {
+        let mut image = image(
+            4,
+            &[(DT_REL, 0x10200), (DT_RELSZ, 16), (DT_NULL, 0)],
+            &[
+                (0x200, 0x10300),
+                (0x204, R_RELATIVE),
+                (0x208, 0x10304),
+                (0x20C, 1),
+                (0x300, 0x10400),
+                (0x304, 0x10500),
+            ],
+        );
+        headers(false).relocate(&mut image, 0x20000);
+        assert_eq!(words(&image, 4, &[0x300, 0x304]), [0x20400, 0x10500]);
+    }
+
+    #[test]
+    fn relocates_rela() {
+        let base = 0x7F00_0000_0000;
+        let mut image = image(
+            8,
+            &[(DT_RELA, 0x10200), (DT_RELASZ, 48), (DT_NULL, 0)],
+            &[
+                (0x200, 0x10300),
+                (0x208, R_RELATIVE),
+                (0x210, 0x10400),
+                (0x218, 0x10308),
+                (0x220, 1),
+                (0x228, 5),
+            ],
+        );
+        headers(true).relocate(&mut image, base);
+        assert_eq!(words(&image, 8, &[0x300, 0x308]), [base as u64 + 0x400, 0]);
+    }
+
+    #[test]
+    fn relocates_relr_bitmap() {
+        let base = 0x7F00_0000_0000;
+        // The address 0x10300, then a bitmap of the first and the third word
+        // after it.
+        let mut image = image(
+            8,
+            &[(DT_RELR, 0x10200), (DT_RELRSZ, 16), (DT_NULL, 0)],
+            &[
+                (0x200, 0x10300),
+                (0x208, 0b1011),
+                (0x300, 0x10000),
+                (0x308, 0x10008),
+                (0x310, 0x10010),
+                (0x318, 0x10018),
+            ],
+        );
+        headers(true).relocate(&mut image, base);
+
+        let base = base as u64;
+        assert_eq!(
+            words(&image, 8, &[0x300, 0x308, 0x310, 0x318]),
+            [base, base + 0x8, 0x10010, base + 0x18]
+        );
+    }
+
+    #[test]
+    fn skips_overflowing_tables() {
+        let mut image = image(
+            8,
+            &[
+                // Below the first segment, wraps around the address space.
+                (DT_REL, 0x10),
+                (DT_RELSZ, 16),
+                (DT_RELA, u64::MAX - 0x10),
+                (DT_RELASZ, 48),
+                (DT_RELR, u64::MAX),
+                (DT_RELRSZ, 16),
+                (DT_NULL, 0),
+            ],
+            &[(0x300, 0x10000)],
+        );
+        let original = image.clone();
+        headers(true).relocate(&mut image, 0x7F00_0000_0000);
+        assert_eq!(image, original);
+    }
+}
@@ -20,28 +20,748 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-extern crate regex;
-use self::regex::bytes::Regex;
-
-/// Enables the user to generate a byte regex out of the normal signature
-/// format.
-pub fn generate_regex(raw: &str) -> Option<Regex> {
-    let mut res = raw
-        .to_string()
-        .split_whitespace()
-        .map(|x| match &x {
-            &"?" => ".".to_string(),
-            x => format!("\\x{}", x),
+use aho_corasick::AhoCorasick;
+use memchr::memmem::Finder;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Fail, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    #[fail(display = "Empty pattern")]
+    Empty,
+
+    #[fail(
+        display = "Invalid token {:?} at position {}: {}",
+        token, position, reason
+    )]
+    InvalidToken {
+        // Index of the first pattern byte the token describes, or of the
+        // mask character.
+        position: usize,
+        token: String,
+        reason: &'static str,
+    },
+
+    #[fail(display = "Mask has {} characters for {} bytes", mask, bytes)]
+    MaskLength { bytes: usize, mask: usize },
+}
+
+/// A byte pattern with wildcards, e.g. `8B 0D ? ? ?? ?? 4? ?F`.
+///
+/// `?` and `??` match any byte, a `?` in place of one hex digit matches any
+/// value of that nibble. Also parsed from the packed x64dbg syntax
+/// (`8B0D????????`) and the code syntax of a byte string and a mask
+/// (`"\x8B\x0D\x00\x00\x00\x00", "xx????"`).
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    // Expected bytes, with wildcard bits cleared.
+    bytes: Vec<u8>,
+
+    // Bits of each byte that have to match.
+    masks: Vec<u8>,
+
+    // Offset of the anchor, the rarest run of fully known bytes.
+    anchor: usize,
+
+    // Searcher for the anchor, `None` if every byte has wildcards.
+    finder: Option<Finder<'static>>,
+}
+
+/// How unlikely `b` is in machine code, higher is rarer.
+///
+/// A coarse guess from x86 code, good enough to avoid anchoring on `00`
+/// or `8B` when the pattern has better bytes.
+fn rarity(b: u8) -> u32 {
+    match b {
+        0x00 => 1,
+        0xCC | 0xFF => 3,
+        0x01 | 0x04 | 0x08 | 0x0F | 0x10 | 0x24 | 0x33 | 0x44 | 0x45 | 0x48 | 0x4C | 0x50
+        | 0x6A | 0x74 | 0x75 | 0x83 | 0x85 | 0x89 | 0x8B | 0x8D | 0xC0 | 0xC3 | 0xE8 | 0xEB => 5,
+        _ => 8,
+    }
+}
+
+/// Find the rarest run of fully known bytes as `(offset, len)`.
+fn select_anchor(bytes: &[u8], masks: &[u8]) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize, u32)> = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        if masks[i] != 0xFF {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < bytes.len() && masks[i] == 0xFF {
+            i += 1;
+        }
+        let score = bytes[start..i].iter().map(|&b| rarity(b)).sum();
+        if best.is_none_or(|(_, _, s)| score > s) {
+            best = Some((start, i - start, score));
+        }
+    }
+    best.map(|(start, len, _)| (start, len))
+}
+
+/// Parse a hex digit or a `?` nibble into `(value, mask)`.
+fn parse_nibble(c: char) -> Option<(u8, u8)> {
+    match c {
+        '?' => Some((0, 0)),
+        c => c.to_digit(16).map(|v| (v as u8, 0xF)),
+    }
+}
+
+impl Pattern {
+    /// Parse a pattern in IDA, x64dbg or code syntax.
+    ///
+    /// The code syntax is detected by its leading `\x` and may be followed by
+    /// a comma and the mask. Without a mask every byte has to match.
+    pub fn parse(raw: &str) -> Result<Self, PatternError> {
+        let raw = raw.trim();
+        if raw.starts_with("\\x") || raw.starts_with("\"\\x") {
+            return match raw.split_once(',') {
+                Some((code, mask)) => Pattern::parse_code(code, mask),
+                None => Pattern::parse_code(raw, &"x".repeat(raw.matches("\\x").count())),
+            };
+        }
+
+        let mut bytes = vec![];
+        let mut masks = vec![];
+        for token in raw.split_whitespace() {
+            let position = bytes.len();
+            let error = |reason| PatternError::InvalidToken {
+                position,
+                token: token.to_string(),
+                reason,
+            };
+
+            let chars = token.chars().collect::<Vec<_>>();
+            if chars == ['?'] {
+                bytes.push(0);
+                masks.push(0);
+                continue;
+            }
+            if chars.len() % 2 != 0 {
+                return Err(error("expected two hex digits per byte"));
+            }
+
+            // x64dbg packs the bytes without spaces, e.g. `A1????????`.
+            for pair in chars.chunks(2) {
+                let (hi, hi_mask) =
+                    parse_nibble(pair[0]).ok_or_else(|| error("not a hex digit"))?;
+                let (lo, lo_mask) =
+                    parse_nibble(pair[1]).ok_or_else(|| error("not a hex digit"))?;
+                bytes.push(hi << 4 | lo);
+                masks.push(hi_mask << 4 | lo_mask);
+            }
+        }
+        Pattern::from_parts(bytes, masks)
+    }
+
+    /// Parse a pattern in code syntax, a byte string of `\x` escapes and a
+    /// mask of `x` for known and `?` for any byte.
+    ///
+    /// Surrounding quotes are ignored, so both can be pasted from source.
+    pub fn parse_code(code: &str, mask: &str) -> Result<Self, PatternError> {
+        let code = code.trim().trim_matches('"');
+        let mask = mask.trim().trim_matches('"');
+
+        let mut pieces = code.split("\\x");
+        match pieces.next() {
+            Some("") | None => {}
+            Some(token) => {
+                return Err(PatternError::InvalidToken {
+                    position: 0,
+                    token: token.to_string(),
+                    reason: "expected \\x escapes",
+                })
+            }
+        }
+
+        let mut bytes = vec![];
+        for (position, piece) in pieces.enumerate() {
+            let byte = match piece.len() {
+                2 => u8::from_str_radix(piece, 16).ok(),
+                _ => None,
+            };
+            bytes.push(byte.ok_or_else(|| PatternError::InvalidToken {
+                position,
+                token: format!("\\x{}", piece),
+                reason: "expected \\x and two hex digits",
+            })?);
+        }
+
+        let count = mask.chars().count();
+        if count != bytes.len() {
+            return Err(PatternError::MaskLength {
+                bytes: bytes.len(),
+                mask: count,
+            });
+        }
+
+        let mut masks = vec![];
+        for (position, c) in mask.chars().enumerate() {
+            match c {
+                'x' | 'X' => masks.push(0xFF),
+                '?' => {
+                    bytes[position] = 0;
+                    masks.push(0);
+                }
+                _ => {
+                    return Err(PatternError::InvalidToken {
+                        position,
+                        token: c.to_string(),
+                        reason: "expected x or ? in mask",
+                    })
+                }
+            }
+        }
+        Pattern::from_parts(bytes, masks)
+    }
+
+    fn from_parts(bytes: Vec<u8>, masks: Vec<u8>) -> Result<Self, PatternError> {
+        if bytes.is_empty() {
+            return Err(PatternError::Empty);
+        }
+
+        let (anchor, finder) = match select_anchor(&bytes, &masks) {
+            Some((start, len)) => {
+                let finder = Finder::new(&bytes[start..start + len]).into_owned();
+                (start, Some(finder))
+            }
+            None => (0, None),
+        };
+        Ok(Pattern {
+            bytes,
+            masks,
+            anchor,
+            finder,
+        })
+    }
+
+    /// Format the pattern in packed x64dbg syntax, e.g. `A1????????`.
+    pub fn to_x64dbg(&self) -> String {
+        self.bytes
+            .iter()
+            .zip(&self.masks)
+            .map(|(&b, &m)| format_byte(b, m, "??"))
+            .collect()
+    }
+
+    /// Format the pattern in code syntax as byte string and mask.
+    ///
+    /// `None` if the pattern has nibble wildcards, which the mask cannot
+    /// express.
+    pub fn to_code(&self) -> Option<(String, String)> {
+        let mut code = String::new();
+        let mut mask = String::new();
+        for (&b, &m) in self.bytes.iter().zip(&self.masks) {
+            match m {
+                0xFF => mask.push('x'),
+                0x00 => mask.push('?'),
+                _ => return None,
+            }
+            code.push_str(&format!("\\x{:02X}", b));
+        }
+        Some((code, mask))
+    }
+
+    /// Number of bytes the pattern matches.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Check if the pattern matches at the start of `data`.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.len()
+            && self
+                .bytes
+                .iter()
+                .zip(&self.masks)
+                .zip(data)
+                .all(|((b, m), d)| d & m == *b)
+    }
+
+    /// Find all matches in `data`, including overlapping ones.
+    pub fn find_all(&self, data: &[u8]) -> Vec<usize> {
+        self.find_iter(data).collect()
+    }
+
+    /// Iterate over the matches in `data`, in order.
+    ///
+    /// Searches for the anchor and verifies the whole pattern around each
+    /// candidate. Candidates may overlap, so the search resumes right after
+    /// the previous one.
+    pub fn find_iter<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        let last = data.len().checked_sub(self.len());
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            let last = last?;
+            loop {
+                if pos > last {
+                    return None;
+                }
+                let i = match self.finder {
+                    Some(ref finder) => {
+                        // Only anchors that leave room for the whole pattern are candidates.
+                        let end = last + self.anchor + finder.needle().len();
+                        pos + finder.find(&data[pos + self.anchor..end])?
+                    }
+                    None => (pos..=last).find(|&i| self.matches(&data[i..]))?,
+                };
+                pos = i + 1;
+                if self.matches(&data[i..]) {
+                    return Some(i);
+                }
+            }
         })
-        .collect::<Vec<_>>()
-        .join("");
-    res.insert_str(0, "(?s-u)");
-    Regex::new(&res).ok()
+    }
+}
+
+/// A set of patterns searched in a single pass.
+///
+/// Runs one Aho-Corasick automaton over the anchors of all patterns and
+/// verifies each candidate against the pattern it belongs to.
+#[derive(Debug, Clone)]
+pub struct PatternSet {
+    patterns: Vec<Pattern>,
+
+    // Automaton over the distinct anchors.
+    automaton: Option<AhoCorasick>,
+
+    // Indices of the patterns sharing each anchor of the automaton.
+    owners: Vec<Vec<usize>>,
+
+    // Patterns without an anchor, searched on their own.
+    unanchored: Vec<usize>,
+}
+
+impl PatternSet {
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        let mut anchors: Vec<&[u8]> = vec![];
+        let mut owners: Vec<Vec<usize>> = vec![];
+        let mut index = HashMap::new();
+        let mut unanchored = vec![];
+        for (i, p) in patterns.iter().enumerate() {
+            let needle = match p.finder {
+                Some(ref finder) => finder.needle(),
+                None => {
+                    unanchored.push(i);
+                    continue;
+                }
+            };
+            let id = *index.entry(needle).or_insert_with(|| {
+                anchors.push(needle);
+                owners.push(vec![]);
+                anchors.len() - 1
+            });
+            owners[id].push(i);
+        }
+
+        let automaton = if anchors.is_empty() {
+            None
+        } else {
+            AhoCorasick::new(&anchors)
+                .map_err(|e| warn!("Could not build pattern automaton: {}", e))
+                .ok()
+        };
+        // Without an automaton every pattern is searched on its own.
+        if automaton.is_none() {
+            unanchored = (0..patterns.len()).collect();
+        }
+
+        PatternSet {
+            patterns,
+            automaton,
+            owners,
+            unanchored,
+        }
+    }
+
+    /// Number of patterns in the set.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Check if the set has no patterns.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Find all matches of every pattern in `data`, by pattern index.
+    pub fn find_all(&self, data: &[u8]) -> Vec<Vec<usize>> {
+        let mut res = vec![vec![]; self.patterns.len()];
+        for &i in &self.unanchored {
+            res[i] = self.patterns[i].find_all(data);
+        }
+
+        let automaton = match self.automaton {
+            Some(ref automaton) => automaton,
+            None => return res,
+        };
+        for m in automaton.find_overlapping_iter(data) {
+            for &i in &self.owners[m.pattern().as_usize()] {
+                let pattern = &self.patterns[i];
+                let start = match m.start().checked_sub(pattern.anchor) {
+                    Some(start) => start,
+                    None => continue,
+                };
+                if pattern.matches(&data[start..]) {
+                    res[i].push(start);
+                }
+            }
+        }
+        res
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(raw: &str) -> Result<Self, PatternError> {
+        Pattern::parse(raw)
+    }
 }
 
-/// Find pattern.
-pub fn find_pattern(data: &[u8], pattern: &str) -> Option<usize> {
-    generate_regex(pattern)
-        .and_then(|r| r.find(data))
-        .and_then(|m| Some(m.start()))
+/// Format a byte with its mask, using `wildcard` for fully unknown bytes.
+fn format_byte(b: u8, m: u8, wildcard: &str) -> String {
+    match m {
+        0xFF => format!("{:02X}", b),
+        0x00 => wildcard.to_string(),
+        0xF0 => format!("{:X}?", b >> 4),
+        _ => format!("?{:X}", b & 0xF),
+    }
+}
+
+/// Formats the pattern in IDA syntax, e.g. `A1 ? ? ? ?`.
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (&b, &m)) in self.bytes.iter().zip(&self.masks).enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", format_byte(b, m, "?"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Pattern {
+        Pattern::parse(raw).unwrap()
+    }
+
+    /// Check the pattern at every offset, the reference for the anchored scan.
+    fn naive_find_all(pattern: &Pattern, data: &[u8]) -> Vec<usize> {
+        (0..data.len())
+            .filter(|&i| pattern.matches(&data[i..]))
+            .collect()
+    }
+
+    /// Pseudo random bytes with the skew of x86 code.
+    fn code_like_data(len: usize) -> Vec<u8> {
+        const COMMON: &[u8] = &[0x00, 0x00, 0xCC, 0xFF, 0x8B, 0x45, 0x0D, 0xE8, 0xA1, 0x33];
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                match state % 4 {
+                    0..=2 => COMMON[(state >> 8) as usize % COMMON.len()],
+                    _ => (state >> 16) as u8,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_wildcards() {
+        let p = parse("8B ? ?? 4? ?F");
+        assert_eq!(p.bytes, [0x8B, 0x00, 0x00, 0x40, 0x0F]);
+        assert_eq!(p.masks, [0xFF, 0x00, 0x00, 0xF0, 0x0F]);
+    }
+
+    #[test]
+    fn matches_nibble_wildcards() {
+        let p = parse("4? ?F");
+        assert!(p.matches(&[0x48, 0x0F]));
+        assert!(p.matches(&[0x4C, 0xFF]));
+        assert!(!p.matches(&[0x58, 0x0F]));
+        assert!(!p.matches(&[0x48, 0x0E]));
+    }
+
+    #[test]
+    fn rejects_empty_pattern() {
+        assert_eq!(Pattern::parse("").unwrap_err(), PatternError::Empty);
+        assert_eq!(Pattern::parse("  ").unwrap_err(), PatternError::Empty);
+    }
+
+    #[test]
+    fn rejects_odd_token() {
+        assert_eq!(
+            Pattern::parse("8B 0D 123").unwrap_err(),
+            PatternError::InvalidToken {
+                position: 2,
+                token: "123".to_string(),
+                reason: "expected two hex digits per byte",
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_non_hex_token() {
+        assert_eq!(
+            Pattern::parse("8B G1").unwrap_err(),
+            PatternError::InvalidToken {
+                position: 1,
+                token: "G1".to_string(),
+                reason: "not a hex digit",
+            }
+        );
+        assert_eq!(
+            Pattern::parse("8B 0D?? ZZ").unwrap_err(),
+            PatternError::InvalidToken {
+                position: 3,
+                token: "ZZ".to_string(),
+                reason: "not a hex digit",
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_mask_length() {
+        assert_eq!(
+            Pattern::parse_code("\\x8B\\x0D", "x").unwrap_err(),
+            PatternError::MaskLength { bytes: 2, mask: 1 }
+        );
+    }
+
+    #[test]
+    fn finds_match_at_end() {
+        let data = [0x00, 0x01, 0x8B, 0x0D];
+        assert_eq!(parse("8B 0D").find_all(&data), [2]);
+        assert_eq!(parse("8B ?").find_all(&data), [2]);
+        assert_eq!(parse("? 0D").find_all(&data), [2]);
+        assert!(parse("8B 0D ?").find_all(&data).is_empty());
+    }
+
+    #[test]
+    fn finds_nothing_in_empty_buffer() {
+        assert!(parse("8B").find_all(&[]).is_empty());
+        assert!(parse("?").find_all(&[]).is_empty());
+    }
+
+    #[test]
+    fn finds_nothing_in_short_buffer() {
+        assert!(parse("8B 0D ? ?").find_all(&[0x8B, 0x0D, 0x00]).is_empty());
+        assert!(parse("? ? ?").find_all(&[0x8B, 0x0D]).is_empty());
+    }
+
+    #[test]
+    fn rates_common_bytes_lower() {
+        assert!(rarity(0x00) < rarity(0xCC));
+        assert!(rarity(0xCC) < rarity(0x8B));
+        assert!(rarity(0x8B) < rarity(0xA1));
+    }
+
+    #[test]
+    fn selects_rarest_run() {
+        // `8B 45` scores 10, `A1 B2 C3` 24 and `00` 1.
+        let p = parse("8B 45 ? A1 B2 C3 ? 00");
+        assert_eq!(select_anchor(&p.bytes, &p.masks), Some((3, 3)));
+        assert_eq!(p.anchor, 3);
+
+        // Ties keep the first run.
+        let p = parse("A1 ? B2");
+        assert_eq!(select_anchor(&p.bytes, &p.masks), Some((0, 1)));
+    }
+
+    #[test]
+    fn selects_no_anchor_without_full_bytes() {
+        let p = parse("? 4? ?F ??");
+        assert_eq!(select_anchor(&p.bytes, &p.masks), None);
+        assert!(p.finder.is_none());
+    }
+
+    #[test]
+    fn anchored_scan_matches_naive_scan() {
+        let mut data = code_like_data(0x10000);
+        data[0x1234..0x123C].copy_from_slice(&[0x8B, 0x45, 0x99, 0xA1, 0xB2, 0xC3, 0x77, 0x00]);
+        for raw in [
+            "8B 45 ? A1 B2 C3 ? 00",
+            "? 4? ?F ?",
+            "00 00 ? ? 8B",
+            "8B 0D",
+            "E8 ? ? ? ? 8B 45",
+            "CC CC",
+            "A1 ?",
+            "? 33",
+        ] {
+            let p = parse(raw);
+            assert_eq!(p.find_all(&data), naive_find_all(&p, &data), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn finds_anchor_at_last_position() {
+        // The anchor `8B` is the last byte of both pattern and buffer.
+        let p = parse("00 00 ? ? 8B");
+        assert_eq!(p.anchor, 4);
+        let data = [0x12, 0x00, 0x00, 0x34, 0x56, 0x8B];
+        assert_eq!(p.find_all(&data), [1]);
+        assert_eq!(p.find_all(&data), naive_find_all(&p, &data));
+    }
+
+    #[test]
+    fn skips_anchor_without_room_for_pattern() {
+        let p = parse("A1 ? ?");
+        assert!(p.find_all(&[0x00, 0x00, 0xA1, 0x00]).is_empty());
+    }
+
+    /// Check that a set finds the same matches as scanning each pattern.
+    fn assert_set_matches(raw: &[&str], data: &[u8]) {
+        let patterns = raw.iter().map(|r| parse(r)).collect::<Vec<_>>();
+        let expected = patterns
+            .iter()
+            .map(|p| p.find_all(data))
+            .collect::<Vec<_>>();
+        assert_eq!(PatternSet::new(patterns).find_all(data), expected);
+    }
+
+    #[test]
+    fn set_matches_single_scans() {
+        let mut data = code_like_data(0x10000);
+        data[0x1234..0x123C].copy_from_slice(&[0x8B, 0x45, 0x99, 0xA1, 0xB2, 0xC3, 0x77, 0x00]);
+        assert_set_matches(
+            &[
+                "8B 45 ? A1 B2 C3 ? 00",
+                "E8 ? ? ? ? 8B 45",
+                "CC CC",
+                "A1 ?",
+                "? 33",
+            ],
+            &data,
+        );
+    }
+
+    #[test]
+    fn set_shares_anchors() {
+        // Both anchor on `8B 0D`, at different offsets.
+        let p = parse("E8 ? ? ? ? 8B 0D");
+        assert_eq!(p.anchor, 5);
+        let data = [
+            0x8B, 0x0D, 0xE8, 0x01, 0x02, 0x03, 0x04, 0x8B, 0x0D, 0x11, 0x22, 0x8B, 0x0D,
+        ];
+        assert_set_matches(&["E8 ? ? ? ? 8B 0D", "8B 0D ? ?", "8B 0D"], &data);
+    }
+
+    #[test]
+    fn set_scans_unanchored_patterns() {
+        let data = [0x48, 0x0F, 0x8B, 0x4C, 0xFF, 0x00];
+        assert_set_matches(&["4? ?F", "? 8B", "8B 4?"], &data);
+        assert_set_matches(&["4? ?F"], &data);
+    }
+
+    #[test]
+    fn set_finds_overlapping_anchors() {
+        let data = [0xAA, 0xAA, 0xAA, 0xAA, 0x12, 0x34, 0x56, 0x78];
+        assert_set_matches(
+            &[
+                "AA AA", "AA AA AA", "? AA AA", "12 34 56", "34 56 78", "34 56",
+            ],
+            &data,
+        );
+    }
+
+    #[test]
+    fn set_handles_empty_buffer() {
+        assert_set_matches(&["AA", "?"], &[]);
+        assert!(PatternSet::new(vec![]).find_all(&[0xAA]).is_empty());
+    }
+
+    fn assert_same(a: &Pattern, b: &Pattern) {
+        assert_eq!((&a.bytes, &a.masks), (&b.bytes, &b.masks));
+    }
+
+    #[test]
+    fn round_trips_between_syntaxes() {
+        for raw in ["8B 0D ? ? ? ? 8B D6", "A1 ? ? ? ? 33 D2 6A 00", "?"] {
+            let ida = parse(raw);
+            assert_eq!(ida.to_string(), raw);
+
+            let x64dbg = parse(&ida.to_x64dbg());
+            assert_same(&ida, &x64dbg);
+
+            let (code, mask) = x64dbg.to_code().unwrap();
+            let code = Pattern::parse_code(&code, &mask).unwrap();
+            assert_same(&ida, &code);
+            assert_eq!(code.to_string(), raw);
+        }
+    }
+
+    #[test]
+    fn formats_nibble_wildcards() {
+        let p = parse("8B 4? ?F ?");
+        assert_eq!(p.to_string(), "8B 4? ?F ?");
+        assert_eq!(p.to_x64dbg(), "8B4??F??");
+        assert_same(&p, &parse(&p.to_x64dbg()));
+        assert_eq!(p.to_code(), None);
+    }
+
+    #[test]
+    fn detects_code_syntax() {
+        let expected = parse("8B 0D ? ?");
+        assert_same(&parse(r#""\x8B\x0D\x00\x00", "xx??""#), &expected);
+        assert_same(&parse(r"\x8B\x0D\x00\x00, xx??"), &expected);
+        assert_same(&parse(r#"  "\x8B\x0D\x12\x34" , "xx??"  "#), &expected);
+
+        // Without a mask every byte has to match.
+        assert_same(&parse(r#""\x8B\x0D""#), &parse("8B 0D"));
+        assert_same(&parse(r"\x8B\x0D"), &parse("8B 0D"));
+    }
+
+    #[test]
+    fn rejects_code_mask_length() {
+        assert_eq!(
+            Pattern::parse(r#""\x8B\x0D", "x""#).unwrap_err(),
+            PatternError::MaskLength { bytes: 2, mask: 1 }
+        );
+        assert_eq!(
+            Pattern::parse(r"\x8B, xx?").unwrap_err(),
+            PatternError::MaskLength { bytes: 1, mask: 3 }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_code_tokens() {
+        assert_eq!(
+            Pattern::parse(r"\x8B\x0G").unwrap_err(),
+            PatternError::InvalidToken {
+                position: 1,
+                token: r"\x0G".to_string(),
+                reason: "expected \\x and two hex digits",
+            }
+        );
+        assert_eq!(
+            Pattern::parse_code(r"8B\x0D", "xx").unwrap_err(),
+            PatternError::InvalidToken {
+                position: 0,
+                token: "8B".to_string(),
+                reason: "expected \\x escapes",
+            }
+        );
+        assert_eq!(
+            Pattern::parse_code(r"\x8B\x0D", "xy").unwrap_err(),
+            PatternError::InvalidToken {
+                position: 1,
+                token: "y".to_string(),
+                reason: "expected x or ? in mask",
+            }
+        );
+    }
 }
@@ -0,0 +1,226 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::memlib::*;
+
+/// Headers of an executable image in any supported format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageHeaders {
+    Pe(PeHeaders),
+    Elf(ElfHeaders),
+}
+
+impl ImageHeaders {
+    /// Parse the headers of a PE or ELF file or mapped image.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        PeHeaders::parse(data)
+            .map(ImageHeaders::Pe)
+            .or_else(|| ElfHeaders::parse(data).map(ImageHeaders::Elf))
+    }
+
+    /// Pointer width of the image in bytes.
+    pub fn pointer_width(&self) -> usize {
+        match self {
+            ImageHeaders::Pe(h) => h.pointer_width(),
+            ImageHeaders::Elf(h) => h.pointer_width(),
+        }
+    }
+
+    /// Address the image prefers to be loaded at.
+    pub fn preferred_base(&self) -> usize {
+        match self {
+            ImageHeaders::Pe(h) => h.image_base as usize,
+            ImageHeaders::Elf(h) => h.min_vaddr(),
+        }
+    }
+
+    /// Size of the image once mapped.
+    pub fn size_of_image(&self) -> usize {
+        match self {
+            ImageHeaders::Pe(h) => h.size_of_image,
+            ImageHeaders::Elf(h) => h.size_of_image(),
+        }
+    }
+}
+
+impl Module {
+    /// Map a PE or ELF file, see `from_pe_file` and `from_elf_file`.
+    pub fn from_image_file(name: &str, file: &[u8], base: Option<usize>) -> Option<Self> {
+        Module::from_pe_file(name, file, base).or_else(|| Module::from_elf_file(name, file, base))
+    }
+}
+
+/// A directory of module files (e.g. `client.dll`, `engine.dll` or
+/// `client_client.so`) copied off a game install, mapped as if they were
+/// loaded into a process.
+#[derive(Debug)]
+pub struct ImageDirectory {
+    // Directory containing the module files.
+    path: PathBuf,
+
+    // Base addresses chosen by the user, by lowercase module name.
+    bases: HashMap<String, usize>,
+
+    // Pointer width of the images.
+    pointer_width: usize,
+
+    // Images in the directory, sorted by name, read once when opening.
+    infos: Vec<ModuleInfo>,
+
+    // Mapped modules.
+    modules: RefCell<HashMap<String, Rc<Module>>>,
+}
+
+/// Returns true if the file name looks like a loadable image.
+pub fn is_image_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".dll")
+        || name.ends_with(".exe")
+        || name.ends_with(".so")
+        || name.contains(".so.")
+}
+
+impl ImageDirectory {
+    /// Open a directory of module files.
+    ///
+    /// `bases` overrides the preferred `ImageBase` of individual modules.
+    pub fn open<P: AsRef<Path>>(path: P, bases: &[(String, usize)]) -> Option<Self> {
+        let mut dir = ImageDirectory {
+            path: path.as_ref().to_path_buf(),
+            bases: bases
+                .iter()
+                .map(|(name, base)| (name.to_lowercase(), *base))
+                .collect(),
+            pointer_width: 0,
+            infos: vec![],
+            modules: RefCell::new(HashMap::new()),
+        };
+
+        let mut widths = vec![];
+        for (name, path) in dir.files() {
+            let headers = match fs::read(&path).ok().and_then(|f| ImageHeaders::parse(&f)) {
+                Some(headers) => headers,
+                None => continue,
+            };
+            widths.push(headers.pointer_width());
+            dir.infos.push(ModuleInfo {
+                base: Va(dir
+                    .base_for(&name)
+                    .unwrap_or_else(|| headers.preferred_base())),
+                size: headers.size_of_image(),
+                path: path.display().to_string(),
+                name,
+            });
+        }
+        if dir.infos.is_empty() {
+            warn!("No module images found in {}", dir.path.display());
+            return None;
+        }
+
+        // Use the width most images agree on, the wider one on a tie.
+        let count = |w| widths.iter().filter(|&&x| x == w).count();
+        dir.pointer_width = if count(8) >= count(4) { 8 } else { 4 };
+        if count(8) != 0 && count(4) != 0 {
+            warn!(
+                "{} mixes 32-bit and 64-bit images, assuming {}-bit",
+                dir.path.display(),
+                dir.pointer_width * 8
+            );
+        }
+
+        debug!(
+            "Opened image directory {} with pointer width {}",
+            dir.path.display(),
+            dir.pointer_width
+        );
+        Some(dir)
+    }
+
+    /// List all image files in the directory, sorted by name.
+    fn files(&self) -> Vec<(String, PathBuf)> {
+        let entries = match fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Could not read directory {}: {}", self.path.display(), e);
+                return vec![];
+            }
+        };
+
+        let mut files = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .filter_map(|e| Some((e.file_name().into_string().ok()?, e.path())))
+            .filter(|(name, _)| is_image_name(name))
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    /// Base address to map the module at, if the user chose one.
+    fn base_for(&self, name: &str) -> Option<usize> {
+        self.bases.get(&name.to_lowercase()).cloned()
+    }
+}
+
+impl MemorySource for ImageDirectory {
+    fn modules(&self) -> Vec<ModuleInfo> {
+        self.infos.clone()
+    }
+
+    fn get_module(&self, name: &str) -> Option<Rc<Module>> {
+        let mut b = self.modules.borrow_mut();
+        if b.contains_key(name) {
+            return b.get(name).cloned();
+        }
+
+        // Module names are case-insensitive on Windows.
+        let info = self
+            .infos
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(name))?;
+        let file = fs::read(&info.path)
+            .map_err(|e| warn!("Could not read {}: {}", info.path, e))
+            .ok()?;
+
+        Module::from_image_file(&info.name, &file, self.base_for(&info.name))
+            .and_then(|m| b.insert(name.to_string(), Rc::new(m)));
+        b.get(name).cloned()
+    }
+
+    fn read_bytes(&self, address: Va, buf: &mut [u8]) -> bool {
+        self.infos
+            .iter()
+            .find(|m| m.contains(address))
+            .and_then(|m| self.get_module(&m.name))
+            .is_some_and(|module| module.read_into(address, buf).is_ok())
+    }
+
+    fn pointer_width(&self) -> usize {
+        self.pointer_width
+    }
+}
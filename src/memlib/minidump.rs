@@ -0,0 +1,319 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+#![allow(clippy::double_parens)]
+
+use nom::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::memlib::*;
+
+/// `MINIDUMP_SIGNATURE` ("MDMP")
+const MINIDUMP_SIGNATURE: u32 = 0x504D_444D;
+
+/// `ModuleListStream`
+const MODULE_LIST_STREAM: u32 = 4;
+
+/// `MemoryListStream`
+const MEMORY_LIST_STREAM: u32 = 5;
+
+/// `SystemInfoStream`
+const SYSTEM_INFO_STREAM: u32 = 7;
+
+/// `Memory64ListStream`
+const MEMORY64_LIST_STREAM: u32 = 9;
+
+/// `PROCESSOR_ARCHITECTURE_INTEL`
+const ARCH_INTEL: u16 = 0;
+
+/// `PROCESSOR_ARCHITECTURE_ARM`
+const ARCH_ARM: u16 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stream {
+    kind: u32,
+    size: usize,
+    rva: u64,
+}
+
+// number_of_streams, stream_directory_rva
+#[rustfmt::skip]
+named!(
+    parse_header<(usize, u64)>,
+    do_parse!(
+        verify!(le_u32, |s| s == MINIDUMP_SIGNATURE) >>
+        take!(4)                                     >>
+        num_streams   : le_u32                       >>
+        directory_rva : le_u32                       >>
+        ((num_streams as usize, u64::from(directory_rva)))
+    )
+);
+
+#[rustfmt::skip]
+named!(
+    parse_stream<Stream>,
+    do_parse!(
+        kind : le_u32 >>
+        size : le_u32 >>
+        rva  : le_u32 >>
+        (Stream {
+            kind,
+            size: size as usize,
+            rva: u64::from(rva),
+        })
+    )
+);
+
+// base_of_image, size_of_image, module_name_rva
+#[rustfmt::skip]
+named!(
+    parse_module<(usize, usize, u64)>,
+    do_parse!(
+        base     : le_u64 >>
+        size     : le_u32 >>
+        take!(8)          >>
+        name_rva : le_u32 >>
+        take!(84)         >>
+        ((base as usize, size as usize, u64::from(name_rva)))
+    )
+);
+
+// start_of_memory_range, data_size, rva
+#[rustfmt::skip]
+named!(
+    parse_memory_descriptor<FileRange>,
+    do_parse!(
+        address : le_u64 >>
+        size    : le_u32 >>
+        rva     : le_u32 >>
+        (FileRange {
+            address: address as usize,
+            size: size as usize,
+            offset: u64::from(rva),
+        })
+    )
+);
+
+// start_of_memory_range, data_size
+#[rustfmt::skip]
+named!(
+    parse_memory_descriptor64<(usize, usize)>,
+    do_parse!(
+        address : le_u64 >>
+        size    : le_u64 >>
+        ((address as usize, size as usize))
+    )
+);
+
+/// A Windows minidump (`.dmp`) file.
+#[derive(Debug)]
+pub struct Minidump {
+    // Memory captured in the dump.
+    memory: DumpMemory,
+
+    // Modules listed in the dump.
+    module_list: Vec<ModuleInfo>,
+
+    // Pointer width of the dumped process.
+    pointer_width: usize,
+
+    // Modules read from the dump.
+    modules: RefCell<HashMap<String, Rc<Module>>>,
+}
+
+/// Read a `MINIDUMP_STRING` at `rva`.
+fn read_string(file: &mut File, rva: u64) -> Option<String> {
+    let raw = read_file_at(file, rva, 4)?;
+    let len = le_u32(&raw).ok()?.1 as usize;
+    let raw = read_file_at(file, rva + 4, len)?;
+    let utf16 = (0..len / 2)
+        .filter_map(|i| read_le::<u16>(&raw, i * 2))
+        .collect::<Vec<_>>();
+    Some(String::from_utf16_lossy(&utf16))
+}
+
+/// Read the contents of a stream.
+fn read_stream(file: &mut File, stream: &Stream) -> Option<Vec<u8>> {
+    read_file_at(file, stream.rva, stream.size)
+}
+
+/// Parse the `ModuleListStream`.
+fn read_modules(file: &mut File, stream: &Stream) -> Option<Vec<ModuleInfo>> {
+    let data = read_stream(file, stream)?;
+    let (rest, count) = le_u32(&data).ok()?;
+    let (_, raw) = count!(rest, parse_module, count as usize).ok()?;
+
+    let mut res = vec![];
+    for (base, size, name_rva) in raw {
+        let path = match read_string(file, name_rva) {
+            Some(path) => path,
+            None => {
+                warn!("Skipping dump module at {:#X}, unreadable name", base);
+                continue;
+            }
+        };
+        let name = crate::helpers::file_name(&path).to_string();
+        debug!("Dump module {} at {:#X} size {:#X}", name, base, size);
+        res.push(ModuleInfo {
+            name,
+            path,
+            base: Va(base),
+            size,
+        });
+    }
+    Some(res)
+}
+
+/// Parse the `MemoryListStream`.
+fn read_memory_list(file: &mut File, stream: &Stream) -> Option<Vec<FileRange>> {
+    let data = read_stream(file, stream)?;
+    let (rest, count) = le_u32(&data).ok()?;
+    count!(rest, parse_memory_descriptor, count as usize)
+        .ok()
+        .map(|(_, r)| r)
+}
+
+/// Parse the `Memory64ListStream`, whose ranges are stored back to back.
+fn read_memory64_list(file: &mut File, stream: &Stream) -> Option<Vec<FileRange>> {
+    let data = read_stream(file, stream)?;
+    let (rest, count) = le_u64(&data).ok()?;
+    let (rest, mut offset) = le_u64(rest).ok()?;
+    let (_, raw) = count!(rest, parse_memory_descriptor64, count as usize).ok()?;
+
+    let mut res = vec![];
+    for (address, size) in raw {
+        res.push(FileRange {
+            address,
+            size,
+            offset,
+        });
+        offset = offset.saturating_add(size as u64);
+    }
+    Some(res)
+}
+
+impl Minidump {
+    /// Open a minidump file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let mut file = File::open(path.as_ref())
+            .map_err(|e| warn!("Could not open {}: {}", path.as_ref().display(), e))
+            .ok()?;
+
+        let raw = read_file_at(&mut file, 0, 16)?;
+        let (_, (num_streams, directory_rva)) = parse_header(&raw)
+            .map_err(|_| warn!("Not a minidump: {}", path.as_ref().display()))
+            .ok()?;
+        let raw = read_file_at(&mut file, directory_rva, num_streams * 12)?;
+        let (_, streams) = count!(&raw[..], parse_stream, num_streams).ok()?;
+
+        let mut module_list = vec![];
+        let mut ranges = vec![];
+        let mut arch = None;
+        for stream in &streams {
+            match stream.kind {
+                MODULE_LIST_STREAM => {
+                    module_list = read_modules(&mut file, stream).unwrap_or_else(|| {
+                        warn!("Could not read the module list of the dump");
+                        vec![]
+                    })
+                }
+                MEMORY_LIST_STREAM => ranges.extend(read_memory_list(&mut file, stream)?),
+                MEMORY64_LIST_STREAM => ranges.extend(read_memory64_list(&mut file, stream)?),
+                SYSTEM_INFO_STREAM => {
+                    arch =
+                        read_stream(&mut file, stream).and_then(|d| le_u16(&d).ok().map(|(_, a)| a))
+                }
+                _ => {}
+            }
+        }
+        debug!(
+            "Minidump: {} modules, {} memory ranges, architecture {:?}",
+            module_list.len(),
+            ranges.len(),
+            arch
+        );
+
+        let mut dump = Minidump {
+            memory: DumpMemory::new(file, ranges),
+            module_list,
+            pointer_width: 8,
+            modules: RefCell::new(HashMap::new()),
+        };
+
+        dump.pointer_width = match arch {
+            Some(ARCH_INTEL) | Some(ARCH_ARM) => 4,
+            // A 32-bit process on a 64-bit system: trust the main executable.
+            _ => dump.main_image_pointer_width().unwrap_or(8),
+        };
+        debug!("Minidump pointer width: {}", dump.pointer_width);
+
+        Some(dump)
+    }
+
+    /// Pointer width of the first module, which is the main executable.
+    fn main_image_pointer_width(&self) -> Option<usize> {
+        let main = self.module_list.first()?;
+        let mut headers = vec![0u8; 0x1000];
+        self.memory.read_sparse(main.base, &mut headers);
+        PeHeaders::parse(&headers).map(|h| h.pointer_width())
+    }
+}
+
+impl MemorySource for Minidump {
+    fn modules(&self) -> Vec<ModuleInfo> {
+        self.module_list.clone()
+    }
+
+    fn get_module(&self, name: &str) -> Option<Rc<Module>> {
+        let mut b = self.modules.borrow_mut();
+        if b.contains_key(name) {
+            return b.get(name).cloned();
+        }
+
+        let info = self
+            .module_list
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(name))?;
+        let mut data = vec![0u8; info.size];
+        if self.memory.read_sparse(info.base, &mut data) == 0 {
+            warn!("Module {} is not contained in the dump", info.name);
+            return None;
+        }
+
+        let regions = Region::from_ranges(info.size, self.memory.present(info.base, info.size));
+        let module = Module::with_regions(&info.name, info.base, data, regions);
+        b.insert(name.to_string(), Rc::new(module));
+        b.get(name).cloned()
+    }
+
+    fn read_bytes(&self, address: Va, buf: &mut [u8]) -> bool {
+        self.memory.read(address, buf)
+    }
+
+    fn pointer_width(&self) -> usize {
+        self.pointer_width
+    }
+}
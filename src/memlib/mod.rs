@@ -20,16 +20,47 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+mod address;
+mod archive;
+mod core;
+mod dump;
+mod elf;
 mod findpattern;
+mod image;
+mod minidump;
 mod module;
+mod pe;
+mod peimage;
+#[cfg(any(windows, target_os = "linux"))]
 mod process;
+mod reader;
+mod rtti;
+#[cfg(windows)]
 mod snapshot;
+mod source;
+mod xref;
 
+pub use self::address::*;
+pub use self::archive::*;
+pub use self::core::*;
+pub use self::dump::*;
+pub use self::elf::*;
 pub use self::findpattern::*;
+pub use self::image::*;
+pub use self::minidump::*;
 pub use self::module::*;
+pub use self::pe::*;
+pub use self::peimage::*;
+#[cfg(any(windows, target_os = "linux"))]
 pub use self::process::*;
+pub use self::reader::*;
+pub use self::rtti::*;
+#[cfg(windows)]
 pub use self::snapshot::*;
+pub use self::source::*;
+pub use self::xref::*;
 
+#[cfg(windows)]
 pub trait Constructor {
     fn new() -> Self;
 }
@@ -20,118 +20,389 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-extern crate winapi;
-
-use self::winapi::shared::minwindef::FALSE;
-use self::winapi::um::tlhelp32::{
-    Module32FirstW,
-    Module32NextW,
-    MODULEENTRY32W,
-    TH32CS_SNAPMODULE,
-    TH32CS_SNAPMODULE32,
-};
 use crate::memlib::*;
-use std::mem;
+use std::cell::OnceCell;
+use std::fmt;
+
+/// Size of the pages that unreadable regions are narrowed down to.
+const PAGE_SIZE: usize = 0x1000;
+
+/// Page protection of a memory region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Protection {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |set, c| if set { c } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(self.read, 'r'),
+            flag(self.write, 'w'),
+            flag(self.execute, 'x')
+        )
+    }
+}
+
+/// A range of a module with uniform protection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    // Offset from the module base.
+    pub offset: usize,
+
+    // Size in bytes.
+    pub size: usize,
+
+    // Page protection, `None` if the backend does not know it.
+    pub protection: Option<Protection>,
+
+    // True if the contents were read, false if they were zero-filled.
+    pub readable: bool,
+}
+
+impl Region {
+    /// A single readable region of unknown protection covering `size` bytes.
+    pub fn whole(size: usize) -> Self {
+        Region {
+            offset: 0,
+            size,
+            protection: None,
+            readable: true,
+        }
+    }
+
+    /// Offset of the first byte after the region.
+    pub fn end(&self) -> usize {
+        self.offset + self.size
+    }
+
+    /// Split `0..size` into readable and unreadable regions, given the
+    /// readable `(start, end)` ranges.
+    pub fn from_ranges(size: usize, mut ranges: Vec<(usize, usize)>) -> Vec<Region> {
+        ranges.sort();
+
+        let mut res: Vec<Region> = vec![];
+        let push = |res: &mut Vec<Region>, offset: usize, end: usize, readable: bool| {
+            if offset >= end {
+                return;
+            }
+            match res.last_mut() {
+                Some(last) if last.readable == readable && last.end() == offset => {
+                    last.size = end - last.offset;
+                }
+                _ => res.push(Region {
+                    offset,
+                    size: end - offset,
+                    protection: None,
+                    readable,
+                }),
+            }
+        };
+
+        let mut pos = 0;
+        for (start, end) in ranges {
+            let start = start.max(pos).min(size);
+            let end = end.min(size);
+            push(&mut res, pos, start, false);
+            push(&mut res, start, end, true);
+            pos = pos.max(end);
+        }
+        push(&mut res, pos, size, false);
+        res
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Module {
     pub name: String,
-    pub base: usize,
+    pub base: Va,
     pub size: usize,
     pub data: Vec<u8>,
-}
 
-impl Constructor for MODULEENTRY32W {
-    /// Create a new instance of `MODULEENTRY32W`
-    fn new() -> Self {
-        let mut module: MODULEENTRY32W = unsafe { mem::zeroed() };
-        module.dwSize = mem::size_of::<MODULEENTRY32W>() as u32;
-        module
-    }
+    // Region map of `data`, sorted by offset and covering the whole module.
+    pub regions: Vec<Region>,
+
+    // PE model, parsed on first use.
+    pe: OnceCell<Option<PeImage>>,
+
+    // RTTI vtables, searched on first use.
+    vtables: OnceCell<Vec<VTable>>,
 }
 
 impl Module {
-    fn from_module_entry(me: &MODULEENTRY32W, name: &str, process: &Process) -> Option<Self> {
-        let mut i = Module {
-            name: name.to_string(),
-            base: me.modBaseAddr as usize,
-            size: me.modBaseSize as usize,
-            data: vec![0u8; me.modBaseSize as usize],
-        };
+    /// Create a module whose `data` is readable as a whole.
+    pub fn new(name: &str, base: Va, data: Vec<u8>) -> Self {
+        let regions = vec![Region::whole(data.len())];
+        Module::with_regions(name, base, data, regions)
+    }
 
-        if process.read_ptr(i.data.as_mut_ptr(), i.base, i.size) {
-            return Some(i);
+    /// Create a module with a known region map.
+    pub fn with_regions(name: &str, base: Va, data: Vec<u8>, regions: Vec<Region>) -> Self {
+        Module {
+            name: name.to_string(),
+            base,
+            size: data.len(),
+            data,
+            regions,
+            pe: OnceCell::new(),
+            vtables: OnceCell::new(),
         }
-
-        None
     }
 
-    pub fn find_pattern(&self, pattern: &str) -> Option<usize> {
-        findpattern::find_pattern(&self.data, pattern)
-    }
+    /// Read a module of `size` bytes at `base` from a `MemorySource`.
+    ///
+    /// Reads region by region and zero-fills the pages that could not be
+    /// read. Fails only if nothing at all could be read.
+    pub fn from_source<S: MemorySource + ?Sized>(
+        name: &str,
+        base: Va,
+        size: usize,
+        source: &S,
+    ) -> Option<Self> {
+        let mut layout = source.query_regions(base, size);
+        if layout.is_empty() {
+            layout.push(Region::whole(size));
+        }
+
+        let mut data = vec![0u8; size];
+        let mut regions: Vec<Region> = vec![];
+        for r in layout {
+            let buf = &mut data[r.offset..r.end()];
+            if !r.readable || source.read_bytes(base + r.offset, buf) {
+                regions.push(r);
+            } else {
+                debug!(
+                    "Could not read region {:#X} of {}, reading pages",
+                    base + r.offset,
+                    name
+                );
+                read_pages(base, r, buf, source, &mut regions);
+            }
+        }
 
-    /// o: Offset
-    /// is_relative: Base has already been subtracted.
-    pub fn get_raw<T: Copy>(&self, mut o: usize, is_relative: bool) -> Option<T> {
-        if !is_relative {
-            o -= self.base;
+        for r in &regions {
+            trace!(
+                "{} region +{:#X} size {:#X} protection {} readable {}",
+                name,
+                r.offset,
+                r.size,
+                r.protection.map_or("?".to_string(), |p| p.to_string()),
+                r.readable
+            );
         }
-        if o + mem::size_of::<T>() >= self.data.len() {
+
+        let unreadable: usize = regions.iter().filter(|r| !r.readable).map(|r| r.size).sum();
+        if unreadable == size {
+            warn!("Module {} is not readable", name);
             return None;
         }
-        let ptr = self.data.get(o)?;
-        let raw: T = unsafe { mem::transmute_copy(ptr) };
-        Some(raw)
+        if unreadable != 0 {
+            warn!(
+                "Module {}: {:#X} of {:#X} bytes unreadable",
+                name, unreadable, size
+            );
+        }
+
+        Some(Module::with_regions(name, base, data, regions))
     }
 
-    /// is_relative: if true, the base has already been subtracted.
-    pub fn get_slice(&self, mut offset: usize, len: usize, is_relative: bool) -> Option<&[u8]> {
-        if !is_relative {
-            offset = offset.wrapping_sub(self.base);
-        }
-        self.data.get(offset..(offset + len))
+    /// The PE model of the module, parsed on first use.
+    ///
+    /// `None` if the module is not a PE image.
+    pub fn pe(&self) -> Option<&PeImage> {
+        self.pe
+            .get_or_init(|| {
+                let pe = PeImage::parse(&self.data);
+                if pe.is_none() {
+                    debug!("Module {} is not a PE image", self.name);
+                }
+                pe
+            })
+            .as_ref()
     }
 
-    /// is_relative: if true, the base has already been subtracted.
-    pub fn get(&self, mut offset: usize, is_relative: bool) -> Option<&[u8]> {
-        if !is_relative {
-            offset = offset.wrapping_sub(self.base);
-        }
-        self.data.get(offset..)
+    /// All class vtables found through MSVC RTTI, searched on first use.
+    pub fn vtables(&self) -> &[VTable] {
+        self.vtables.get_or_init(|| match self.pe() {
+            Some(pe) => find_vtables(self, pe.pointer_width),
+            None => vec![],
+        })
     }
-}
 
-/// Wrapper around the `Module32FirstW` windows api
-fn module32_first(h: &SnapshotHandle, me: &mut MODULEENTRY32W) -> bool {
-    unsafe { Module32FirstW(**h, me) != FALSE }
-}
+    /// Get the primary vtable of `class`, by demangled or mangled name.
+    pub fn find_vtable(&self, class: &str) -> Option<&VTable> {
+        self.vtables()
+            .iter()
+            .filter(|v| v.offset == 0)
+            .find(|v| v.class == class || v.mangled == class)
+    }
 
-/// Wrapper around the `Module32NextW` windows api
-fn module32_next(h: &SnapshotHandle, me: &mut MODULEENTRY32W) -> bool {
-    unsafe { Module32NextW(**h, me) != FALSE }
-}
+    /// The RVA of `va`, if it lies inside the module.
+    pub fn rva(&self, va: Va) -> Option<Rva> {
+        va.0.checked_sub(self.base.0)
+            .filter(|&rva| rva < self.size)
+            .map(Rva)
+    }
+
+    /// The VA of `rva`.
+    pub fn va(&self, rva: Rva) -> Va {
+        self.base + rva.0
+    }
+
+    /// Check if `va` lies inside the module.
+    pub fn contains(&self, va: Va) -> bool {
+        self.rva(va).is_some()
+    }
+
+    /// Get the region containing `rva`.
+    pub fn region_at(&self, rva: Rva) -> Option<&Region> {
+        let i = self.regions.partition_point(|r| r.end() <= rva.0);
+        self.regions.get(i).filter(|r| r.offset <= rva.0)
+    }
+
+    /// Check if all `len` bytes at `rva` were read.
+    pub fn is_readable(&self, rva: Rva, len: usize) -> bool {
+        let end = match rva.0.checked_add(len) {
+            Some(end) if end <= self.data.len() => end,
+            _ => return false,
+        };
 
-pub fn get(name: &str, process: &Process) -> Option<Module> {
-    let snapshot = SnapshotHandle::new(process.id, TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32)?;
-    let mut me = MODULEENTRY32W::new();
+        let mut pos = rva;
+        while pos.0 < end {
+            match self.region_at(pos) {
+                Some(r) if r.readable => pos = Rva(r.end()),
+                _ => return false,
+            }
+        }
+        true
+    }
 
-    if !module32_first(&snapshot, &mut me) {
-        return None;
+    /// Contiguous readable ranges of `data`.
+    fn readable_ranges(&self) -> Vec<(usize, usize)> {
+        let mut res: Vec<(usize, usize)> = vec![];
+        for r in self.regions.iter().filter(|r| r.readable) {
+            match res.last_mut() {
+                Some(last) if last.1 == r.offset => last.1 = r.end(),
+                _ => res.push((r.offset, r.end())),
+            }
+        }
+        res
+    }
+
+    /// Find all matches of `pattern` in the readable parts of the module.
+    pub fn find_pattern_all(&self, pattern: &Pattern) -> Vec<Rva> {
+        self.readable_ranges()
+            .into_iter()
+            .flat_map(|(start, end)| {
+                pattern
+                    .find_all(&self.data[start..end])
+                    .into_iter()
+                    .map(move |o| Rva(o + start))
+            })
+            .collect()
+    }
+
+    /// Find all matches of every pattern of `set` in the readable parts of
+    /// the module in a single pass, by pattern index.
+    pub fn find_patterns(&self, set: &PatternSet) -> Vec<Vec<Rva>> {
+        let mut res = vec![vec![]; set.len()];
+        if set.is_empty() {
+            return res;
+        }
+        for (start, end) in self.readable_ranges() {
+            let found = set.find_all(&self.data[start..end]);
+            for (r, o) in res.iter_mut().zip(found) {
+                r.extend(o.into_iter().map(|o| Rva(o + start)));
+            }
+        }
+        res
     }
 
-    loop {
-        let s = String::from_utf16_lossy(&me.szModule)
-            .trim_matches('\0')
-            .to_string();
+    /// Get `len` bytes at `rva`.
+    pub fn get_slice(&self, rva: Rva, len: usize) -> Option<&[u8]> {
+        self.data.get(rva.0..rva.0.checked_add(len)?)
+    }
+}
 
-        if name == s {
-            return Module::from_module_entry(&me, &s, process);
+/// Read the region `r` page by page, recording which pages were readable.
+fn read_pages<S: MemorySource + ?Sized>(
+    base: Va,
+    r: Region,
+    buf: &mut [u8],
+    source: &S,
+    regions: &mut Vec<Region>,
+) {
+    let mut pos = 0;
+    while pos < buf.len() {
+        // Pages are aligned to the target address, not the region.
+        let addr = base + r.offset + pos;
+        let len = (PAGE_SIZE - addr.0 % PAGE_SIZE).min(buf.len() - pos);
+        let readable = source.read_bytes(addr, &mut buf[pos..pos + len]);
+        if !readable {
+            buf[pos..pos + len].iter_mut().for_each(|b| *b = 0);
         }
 
-        if !module32_next(&snapshot, &mut me) {
-            break;
+        match regions.last_mut() {
+            Some(last)
+                if last.readable == readable
+                    && last.protection == r.protection
+                    && last.end() == r.offset + pos =>
+            {
+                last.size += len
+            }
+            _ => regions.push(Region {
+                offset: r.offset + pos,
+                size: len,
+                protection: r.protection,
+                readable,
+            }),
         }
+        pos += len;
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    None
+    #[test]
+    fn splits_ranges_into_regions() {
+        let region = |offset, end, readable| Region {
+            offset,
+            size: end - offset,
+            protection: None,
+            readable,
+        };
+
+        // Unsorted, overlapping and reaching past the end of the module.
+        let ranges = vec![
+            (0x3000, 0x4000),
+            (0, 0x1000),
+            (0x800, 0x1800),
+            (0x4800, 0x9000),
+        ];
+        assert_eq!(
+            Region::from_ranges(0x5000, ranges),
+            vec![
+                region(0, 0x1800, true),
+                region(0x1800, 0x3000, false),
+                region(0x3000, 0x4000, true),
+                region(0x4000, 0x4800, false),
+                region(0x4800, 0x5000, true),
+            ]
+        );
+
+        assert_eq!(
+            Region::from_ranges(0x2000, vec![]),
+            vec![region(0, 0x2000, false)]
+        );
+        assert_eq!(
+            Region::from_ranges(0x2000, vec![(0x1000, 0x2000), (0, 0x1000)]),
+            vec![Region::whole(0x2000)]
+        );
+    }
 }
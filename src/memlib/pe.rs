@@ -0,0 +1,430 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+#![allow(clippy::double_parens)]
+
+use nom::*;
+use std::cmp;
+
+use crate::memlib::{read_word, Module, Va};
+
+/// `IMAGE_NT_OPTIONAL_HDR32_MAGIC`
+const PE32_MAGIC: u16 = 0x10B;
+
+/// `IMAGE_NT_OPTIONAL_HDR64_MAGIC`
+const PE32_PLUS_MAGIC: u16 = 0x20B;
+
+/// `IMAGE_NUMBEROF_DIRECTORY_ENTRIES`
+const MAX_DATA_DIRECTORIES: u32 = 16;
+
+/// `IMAGE_DIRECTORY_ENTRY_BASERELOC`
+pub const DIRECTORY_BASERELOC: usize = 5;
+
+/// `IMAGE_DIRECTORY_ENTRY_IAT`
+pub const DIRECTORY_IAT: usize = 12;
+
+/// Size of `IMAGE_FILE_HEADER` including the `PE\0\0` signature.
+const FILE_HEADER_SIZE: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SectionHeader {
+    pub name: String,
+    pub virtual_size: usize,
+    pub virtual_address: usize,
+    pub raw_size: usize,
+    pub raw_offset: usize,
+    pub characteristics: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DataDirectory {
+    pub rva: usize,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct OptionalHeader {
+    is_64bit: bool,
+    image_base: u64,
+    section_alignment: usize,
+    file_alignment: usize,
+    size_of_image: usize,
+    size_of_headers: usize,
+    checksum: u32,
+    data_directories: Vec<DataDirectory>,
+}
+
+/// The parts of the PE headers needed to map and inspect an image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeHeaders {
+    pub machine: u16,
+    pub timestamp: u32,
+    pub is_64bit: bool,
+    pub image_base: u64,
+    pub section_alignment: usize,
+    pub file_alignment: usize,
+    pub size_of_image: usize,
+    pub size_of_headers: usize,
+    pub checksum: u32,
+    pub data_directories: Vec<DataDirectory>,
+    pub sections: Vec<SectionHeader>,
+
+    // Position of the `ImageBase` field, patched when rebasing.
+    image_base_offset: usize,
+}
+
+// e_lfanew
+#[rustfmt::skip]
+named!(
+    parse_dos_header<usize>,
+    do_parse!(
+        tag!("MZ")        >>
+        take!(0x3A)       >>
+        e_lfanew : le_u32 >>
+        (e_lfanew as usize)
+    )
+);
+
+// machine, number_of_sections, timestamp, size_of_optional_header
+#[rustfmt::skip]
+named!(
+    parse_file_header<(u16, usize, u32, usize)>,
+    do_parse!(
+        tag!(b"PE\0\0")            >>
+        machine         : le_u16 >>
+        num_sections    : le_u16 >>
+        timestamp       : le_u32 >>
+        take!(8)                 >>
+        size_opt_header : le_u16 >>
+        take!(2)                 >>
+        ((
+            machine,
+            num_sections as usize,
+            timestamp,
+            size_opt_header as usize,
+        ))
+    )
+);
+
+#[rustfmt::skip]
+named!(
+    parse_data_directory<DataDirectory>,
+    do_parse!(
+        rva  : le_u32 >>
+        size : le_u32 >>
+        (DataDirectory {
+            rva: rva as usize,
+            size: size as usize,
+        })
+    )
+);
+
+#[rustfmt::skip]
+named!(
+    parse_optional_header32<OptionalHeader>,
+    do_parse!(
+        take!(26)                  >>
+        image_base        : le_u32 >>
+        section_alignment : le_u32 >>
+        file_alignment    : le_u32 >>
+        take!(16)                  >>
+        size_of_image     : le_u32 >>
+        size_of_headers   : le_u32 >>
+        checksum          : le_u32 >>
+        take!(24)                  >>
+        num_dirs          : le_u32 >>
+        data_directories  : count!(
+            parse_data_directory,
+            cmp::min(num_dirs, MAX_DATA_DIRECTORIES) as usize
+        ) >>
+        (OptionalHeader {
+            is_64bit: false,
+            image_base: u64::from(image_base),
+            section_alignment: section_alignment as usize,
+            file_alignment: file_alignment as usize,
+            size_of_image: size_of_image as usize,
+            size_of_headers: size_of_headers as usize,
+            checksum,
+            data_directories,
+        })
+    )
+);
+
+#[rustfmt::skip]
+named!(
+    parse_optional_header64<OptionalHeader>,
+    do_parse!(
+        take!(22)                  >>
+        image_base        : le_u64 >>
+        section_alignment : le_u32 >>
+        file_alignment    : le_u32 >>
+        take!(16)                  >>
+        size_of_image     : le_u32 >>
+        size_of_headers   : le_u32 >>
+        checksum          : le_u32 >>
+        take!(40)                  >>
+        num_dirs          : le_u32 >>
+        data_directories  : count!(
+            parse_data_directory,
+            cmp::min(num_dirs, MAX_DATA_DIRECTORIES) as usize
+        ) >>
+        (OptionalHeader {
+            is_64bit: true,
+            image_base,
+            section_alignment: section_alignment as usize,
+            file_alignment: file_alignment as usize,
+            size_of_image: size_of_image as usize,
+            size_of_headers: size_of_headers as usize,
+            checksum,
+            data_directories,
+        })
+    )
+);
+
+named!(
+    parse_optional_header<OptionalHeader>,
+    switch!(le_u16,
+        PE32_MAGIC => call!(parse_optional_header32) |
+        PE32_PLUS_MAGIC => call!(parse_optional_header64)
+    )
+);
+
+#[rustfmt::skip]
+named!(
+    parse_section_header<SectionHeader>,
+    do_parse!(
+        name            : take!(8) >>
+        virtual_size    : le_u32   >>
+        virtual_address : le_u32   >>
+        raw_size        : le_u32   >>
+        raw_offset      : le_u32   >>
+        take!(12)                  >>
+        characteristics : le_u32   >>
+        (SectionHeader {
+            name: String::from_utf8_lossy(name).trim_end_matches('\0').to_string(),
+            virtual_size: virtual_size as usize,
+            virtual_address: virtual_address as usize,
+            raw_size: raw_size as usize,
+            raw_offset: raw_offset as usize,
+            characteristics,
+        })
+    )
+);
+
+// page_rva, block_size
+#[rustfmt::skip]
+named!(
+    parse_reloc_block<(usize, usize)>,
+    do_parse!(
+        page_rva   : le_u32 >>
+        block_size : le_u32 >>
+        ((page_rva as usize, block_size as usize))
+    )
+);
+
+/// Round `value` up to the next multiple of `alignment`.
+fn align_up(value: usize, alignment: usize) -> usize {
+    match alignment {
+        0 => value,
+        a => value.div_ceil(a) * a,
+    }
+}
+
+impl PeHeaders {
+    /// Parse the headers of a PE file or of a mapped PE image.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let (_, e_lfanew) = parse_dos_header(data).ok()?;
+        let (_, (machine, num_sections, timestamp, size_opt_header)) =
+            parse_file_header(data.get(e_lfanew..)?).ok()?;
+
+        let opt_offset = e_lfanew + FILE_HEADER_SIZE;
+        let (_, opt) = parse_optional_header(data.get(opt_offset..)?).ok()?;
+
+        let sections_offset = opt_offset + size_opt_header;
+        let (_, sections) = count!(
+            data.get(sections_offset..)?,
+            parse_section_header,
+            num_sections
+        )
+        .ok()?;
+
+        Some(PeHeaders {
+            machine,
+            timestamp,
+            is_64bit: opt.is_64bit,
+            image_base: opt.image_base,
+            section_alignment: opt.section_alignment,
+            file_alignment: opt.file_alignment,
+            size_of_image: opt.size_of_image,
+            size_of_headers: opt.size_of_headers,
+            checksum: opt.checksum,
+            data_directories: opt.data_directories,
+            sections,
+            image_base_offset: opt_offset + if opt.is_64bit { 24 } else { 28 },
+        })
+    }
+
+    /// Pointer width of the image in bytes.
+    pub fn pointer_width(&self) -> usize {
+        if self.is_64bit {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Get a data directory by its `IMAGE_DIRECTORY_ENTRY_*` index.
+    pub fn data_directory(&self, index: usize) -> Option<DataDirectory> {
+        self.data_directories
+            .get(index)
+            .cloned()
+            .filter(|d| d.rva != 0 && d.size != 0)
+    }
+
+    /// Lay out the sections of a PE file the way the Windows loader does.
+    pub fn map(&self, file: &[u8]) -> Vec<u8> {
+        let mut image = vec![0u8; self.size_of_image];
+
+        let headers = cmp::min(cmp::min(self.size_of_headers, file.len()), image.len());
+        image[..headers].copy_from_slice(&file[..headers]);
+
+        for section in &self.sections {
+            // The loader ignores the low bits of `PointerToRawData`.
+            let raw_offset = section.raw_offset & !0x1FF;
+            let virtual_size = match section.virtual_size {
+                0 => section.raw_size,
+                s => s,
+            };
+            let mapped = align_up(virtual_size, self.section_alignment);
+
+            let len = cmp::min(section.raw_size, mapped);
+            let len = cmp::min(len, file.len().saturating_sub(raw_offset));
+            let len = cmp::min(len, image.len().saturating_sub(section.virtual_address));
+            if len == 0 {
+                continue;
+            }
+
+            trace!(
+                "Mapping section {} ({:#X} bytes) to {:#X}",
+                section.name,
+                len,
+                section.virtual_address
+            );
+            image[section.virtual_address..section.virtual_address + len]
+                .copy_from_slice(&file[raw_offset..raw_offset + len]);
+        }
+
+        image
+    }
+
+    /// Slots patched by the base relocations of a mapped image, as
+    /// `(rva, width)`.
+    pub fn relocations(&self, image: &[u8]) -> Vec<(usize, usize)> {
+        let mut res = vec![];
+        let dir = match self.data_directory(DIRECTORY_BASERELOC) {
+            Some(dir) => dir,
+            None => return res,
+        };
+
+        let end = cmp::min(dir.rva.saturating_add(dir.size), image.len());
+        let mut pos = dir.rva;
+        while pos + 8 <= end {
+            let (page_rva, block_size) = match parse_reloc_block(&image[pos..end]) {
+                Ok((_, b)) => b,
+                Err(_) => break,
+            };
+            if block_size < 8 {
+                break;
+            }
+
+            let entries = cmp::min(pos + block_size, end);
+            for i in (pos + 8..entries).step_by(2) {
+                if i + 2 > entries {
+                    break;
+                }
+                let entry = u16::from_le_bytes([image[i], image[i + 1]]);
+                let target = page_rva + (entry & 0xFFF) as usize;
+
+                match entry >> 12 {
+                    // IMAGE_REL_BASED_HIGHLOW
+                    3 => res.push((target, 4)),
+                    // IMAGE_REL_BASED_DIR64
+                    10 => res.push((target, 8)),
+                    // IMAGE_REL_BASED_ABSOLUTE is padding.
+                    _ => {}
+                }
+            }
+
+            pos += block_size;
+        }
+        res
+    }
+
+    /// Apply the base relocations of a mapped image for a new `base`.
+    pub fn relocate(&self, image: &mut [u8], base: u64) {
+        let delta = base.wrapping_sub(self.image_base);
+        if delta == 0 {
+            return;
+        }
+
+        if let Some(slot) = image.get_mut(self.image_base_offset..) {
+            if self.is_64bit && slot.len() >= 8 {
+                slot[..8].copy_from_slice(&base.to_le_bytes());
+            } else if slot.len() >= 4 {
+                slot[..4].copy_from_slice(&(base as u32).to_le_bytes());
+            }
+        }
+
+        if self.data_directory(DIRECTORY_BASERELOC).is_none() {
+            warn!("Image has no relocations, pointers will use the preferred base");
+            return;
+        }
+
+        for (target, width) in self.relocations(image) {
+            let slot = match image.get_mut(target..target + width) {
+                Some(slot) => slot,
+                None => continue,
+            };
+            let value = read_word(slot, 0, width).unwrap_or(0).wrapping_add(delta);
+            slot.copy_from_slice(&value.to_le_bytes()[..width]);
+        }
+    }
+}
+
+impl Module {
+    /// Map a PE file as if the loader had placed it at `base`.
+    ///
+    /// Uses the preferred `ImageBase` if no `base` is given.
+    pub fn from_pe_file(name: &str, file: &[u8], base: Option<usize>) -> Option<Self> {
+        let headers = PeHeaders::parse(file)?;
+        let base = base.unwrap_or(headers.image_base as usize);
+        debug!(
+            "Mapping PE image {}: ImageBase {:#X} SizeOfImage {:#X} => base {:#X}",
+            name, headers.image_base, headers.size_of_image, base
+        );
+
+        let mut data = headers.map(file);
+        headers.relocate(&mut data, base as u64);
+
+        Some(Module::new(name, Va(base), data))
+    }
+}
@@ -0,0 +1,459 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+#![allow(clippy::double_parens)]
+
+use nom::*;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::memlib::*;
+
+/// `IMAGE_DIRECTORY_ENTRY_EXPORT`
+pub const DIRECTORY_EXPORT: usize = 0;
+
+/// `IMAGE_DIRECTORY_ENTRY_IMPORT`
+pub const DIRECTORY_IMPORT: usize = 1;
+
+/// `IMAGE_DIRECTORY_ENTRY_DEBUG`
+pub const DIRECTORY_DEBUG: usize = 6;
+
+/// `IMAGE_DEBUG_TYPE_CODEVIEW`
+const DEBUG_TYPE_CODEVIEW: u32 = 2;
+
+/// `IMAGE_SCN_CNT_CODE`
+const SCN_CNT_CODE: u32 = 0x20;
+
+/// `IMAGE_SCN_MEM_EXECUTE`
+const SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+
+/// `IMAGE_SCN_MEM_WRITE`
+const SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// Upper bound for names read from the image.
+const MAX_NAME_LEN: usize = 0x1000;
+
+/// Upper bound for chains of forwarded exports.
+const MAX_FORWARDS: usize = 8;
+
+/// An exported symbol.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Export {
+    // Export name, `None` if exported by ordinal only.
+    pub name: Option<String>,
+
+    // Biased ordinal.
+    pub ordinal: u32,
+
+    // RVA of the symbol, or of the forwarder string.
+    pub rva: usize,
+
+    // `module.function` the export is forwarded to.
+    pub forwarder: Option<String>,
+}
+
+/// A function imported from another module.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportedFunction {
+    // Function name, `None` if imported by ordinal.
+    pub name: Option<String>,
+
+    // Ordinal, if imported by ordinal.
+    pub ordinal: Option<u16>,
+
+    // RVA of the import address table slot.
+    pub iat_rva: usize,
+}
+
+/// All functions imported from one module.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Import {
+    // Name of the imported module, e.g. `tier0.dll`.
+    pub module: String,
+
+    // Imported functions.
+    pub functions: Vec<ImportedFunction>,
+}
+
+/// CodeView (`RSDS`) debug information, identifying the matching PDB.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CodeView {
+    // PDB signature GUID.
+    pub guid: String,
+
+    // PDB age.
+    pub age: u32,
+
+    // Path of the PDB at link time.
+    pub pdb_path: String,
+}
+
+impl CodeView {
+    /// The identifier symbol servers use: GUID without dashes and age.
+    pub fn pdb_id(&self) -> String {
+        format!("{}{:X}", self.guid.replace('-', ""), self.age)
+    }
+}
+
+/// Format a GUID in the usual `XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX` form.
+fn format_guid(g: &[u8]) -> String {
+    format!(
+        "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+        u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+        u16::from_le_bytes([g[4], g[5]]),
+        u16::from_le_bytes([g[6], g[7]]),
+        g[8],
+        g[9],
+        g[10],
+        g[11],
+        g[12],
+        g[13],
+        g[14],
+        g[15]
+    )
+}
+
+impl fmt::Display for CodeView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.pdb_path, self.pdb_id())
+    }
+}
+
+impl SectionHeader {
+    /// Check if the section contains executable code.
+    pub fn is_code(&self) -> bool {
+        self.characteristics & (SCN_CNT_CODE | SCN_MEM_EXECUTE) != 0
+    }
+
+    /// Check if the section is mapped with write access.
+    pub fn is_writable(&self) -> bool {
+        self.characteristics & SCN_MEM_WRITE != 0
+    }
+
+    /// Size of the section once mapped.
+    pub fn mapped_size(&self) -> usize {
+        match self.virtual_size {
+            0 => self.raw_size,
+            s => s,
+        }
+    }
+}
+
+/// The PE model of a mapped module.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeImage {
+    // `IMAGE_FILE_HEADER.TimeDateStamp`
+    pub timestamp: u32,
+
+    // `IMAGE_OPTIONAL_HEADER.CheckSum`
+    pub checksum: u32,
+
+    // `SizeOfImage`
+    pub size_of_image: usize,
+
+    // Pointer width of the image in bytes.
+    pub pointer_width: usize,
+
+    // Section table.
+    pub sections: Vec<SectionHeader>,
+
+    // Export table, sorted by ordinal.
+    pub exports: Vec<Export>,
+
+    // Import table.
+    pub imports: Vec<Import>,
+
+    // CodeView debug information.
+    pub codeview: Option<CodeView>,
+}
+
+// base, number_of_functions, number_of_names, address_of_functions,
+// address_of_names, address_of_name_ordinals
+#[rustfmt::skip]
+named!(
+    parse_export_directory<(u32, usize, usize, usize, usize, usize)>,
+    do_parse!(
+        take!(16)                   >>
+        base               : le_u32 >>
+        num_functions      : le_u32 >>
+        num_names          : le_u32 >>
+        functions          : le_u32 >>
+        names              : le_u32 >>
+        ordinals           : le_u32 >>
+        ((
+            base,
+            num_functions as usize,
+            num_names as usize,
+            functions as usize,
+            names as usize,
+            ordinals as usize,
+        ))
+    )
+);
+
+// original_first_thunk, name, first_thunk
+#[rustfmt::skip]
+named!(
+    parse_import_descriptor<(usize, usize, usize)>,
+    do_parse!(
+        original_first_thunk : le_u32 >>
+        take!(8)                      >>
+        name                 : le_u32 >>
+        first_thunk          : le_u32 >>
+        ((
+            original_first_thunk as usize,
+            name as usize,
+            first_thunk as usize,
+        ))
+    )
+);
+
+// type, size_of_data, address_of_raw_data
+#[rustfmt::skip]
+named!(
+    parse_debug_directory<(u32, usize, usize)>,
+    do_parse!(
+        take!(12)             >>
+        kind         : le_u32 >>
+        size_of_data : le_u32 >>
+        address      : le_u32 >>
+        take!(4)              >>
+        ((kind, size_of_data as usize, address as usize))
+    )
+);
+
+#[rustfmt::skip]
+named!(
+    parse_rsds<CodeView>,
+    do_parse!(
+        tag!("RSDS")                      >>
+        guid     : take!(16)              >>
+        age      : le_u32                 >>
+        pdb_path : take_until!("\0")      >>
+        (CodeView {
+            guid: format_guid(guid),
+            age,
+            pdb_path: String::from_utf8_lossy(pdb_path).into_owned(),
+        })
+    )
+);
+
+/// Read a NUL-terminated string at `rva`.
+fn read_name(data: &[u8], rva: usize) -> Option<String> {
+    let raw = data.get(rva..)?;
+    let raw = &raw[..raw.len().min(MAX_NAME_LEN)];
+    let len = raw.iter().position(|&c| c == 0)?;
+    Some(String::from_utf8_lossy(&raw[..len]).into_owned())
+}
+
+/// Parse the export table of a mapped image.
+fn parse_exports(data: &[u8], headers: &PeHeaders) -> Vec<Export> {
+    let dir = match headers.data_directory(DIRECTORY_EXPORT) {
+        Some(dir) => dir,
+        None => return vec![],
+    };
+    let (base, num_functions, num_names, functions, names, ordinals) =
+        match data.get(dir.rva..).map(parse_export_directory) {
+            Some(Ok((_, d))) => d,
+            _ => {
+                warn!("Invalid export directory at {:#X}", dir.rva);
+                return vec![];
+            }
+        };
+
+    // The table is bounded by the image, so corrupt counts are harmless.
+    let mut res = (0..num_functions)
+        .map_while(|i| {
+            let rva = read_le::<u32>(data, functions + i * 4)? as usize;
+            let forwarder = if rva >= dir.rva && rva < dir.rva + dir.size {
+                read_name(data, rva)
+            } else {
+                None
+            };
+            Some(Export {
+                name: None,
+                ordinal: base.wrapping_add(i as u32),
+                rva,
+                forwarder,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for i in 0..num_names {
+        let name = read_le::<u32>(data, names + i * 4).and_then(|r| read_name(data, r as usize));
+        let index = read_le::<u16>(data, ordinals + i * 2);
+        if let (Some(name), Some(index)) = (name, index) {
+            if let Some(export) = res.get_mut(index as usize) {
+                export.name = Some(name);
+            }
+        }
+    }
+
+    // Unused slots of the address table are zero.
+    res.retain(|e| e.rva != 0);
+    res
+}
+
+/// Parse the import table of a mapped image.
+fn parse_imports(data: &[u8], headers: &PeHeaders) -> Vec<Import> {
+    let dir = match headers.data_directory(DIRECTORY_IMPORT) {
+        Some(dir) => dir,
+        None => return vec![],
+    };
+    let width = headers.pointer_width();
+    let ordinal_flag = 1u64 << (width * 8 - 1);
+
+    let mut res = vec![];
+    let mut pos = dir.rva;
+    while let Some(Ok((_, (lookup, name, iat)))) = data.get(pos..).map(parse_import_descriptor) {
+        if name == 0 && iat == 0 {
+            break;
+        }
+        pos += 20;
+
+        let module = match read_name(data, name) {
+            Some(module) => module,
+            None => continue,
+        };
+
+        // Bound imports have no lookup table, use the IAT instead.
+        let lookup = if lookup != 0 { lookup } else { iat };
+        let mut functions = vec![];
+        for i in 0.. {
+            let slot = lookup + i * width;
+            let thunk = read_word(data, slot, width);
+            let thunk = match thunk {
+                Some(0) | None => break,
+                Some(t) => t,
+            };
+
+            let (name, ordinal) = if thunk & ordinal_flag != 0 {
+                (None, Some(thunk as u16))
+            } else {
+                // Skip the `Hint` of `IMAGE_IMPORT_BY_NAME`.
+                (read_name(data, (thunk as usize).wrapping_add(2)), None)
+            };
+            functions.push(ImportedFunction {
+                name,
+                ordinal,
+                iat_rva: iat + i * width,
+            });
+        }
+
+        res.push(Import { module, functions });
+    }
+    res
+}
+
+/// Find the CodeView entry of the debug directory.
+fn parse_codeview(data: &[u8], headers: &PeHeaders) -> Option<CodeView> {
+    let dir = headers.data_directory(DIRECTORY_DEBUG)?;
+    (0..dir.size / 28)
+        .filter_map(|i| parse_debug_directory(data.get(dir.rva + i * 28..)?).ok())
+        .map(|(_, d)| d)
+        .filter(|&(kind, _, _)| kind == DEBUG_TYPE_CODEVIEW)
+        .find_map(|(_, size, rva)| {
+            let raw = data.get(rva..rva.checked_add(size)?)?;
+            parse_rsds(raw).ok().map(|(_, cv)| cv)
+        })
+}
+
+impl PeImage {
+    /// Parse the PE model of a mapped image.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let headers = PeHeaders::parse(data)?;
+        Some(PeImage {
+            timestamp: headers.timestamp,
+            checksum: headers.checksum,
+            size_of_image: headers.size_of_image,
+            pointer_width: headers.pointer_width(),
+            exports: parse_exports(data, &headers),
+            imports: parse_imports(data, &headers),
+            codeview: parse_codeview(data, &headers),
+            sections: headers.sections,
+        })
+    }
+
+    /// Get an export by name, or by ordinal as `#<ordinal>`.
+    pub fn export(&self, name: &str) -> Option<&Export> {
+        if let Some(ordinal) = name.strip_prefix('#') {
+            let ordinal = ordinal.parse::<u32>().ok()?;
+            return self.exports.iter().find(|e| e.ordinal == ordinal);
+        }
+        self.exports
+            .iter()
+            .find(|e| e.name.as_ref().is_some_and(|n| n == name))
+    }
+}
+
+/// Resolve the export `name` of `module`, following forwarders.
+///
+/// Returns the module that finally contains the export and its RVA.
+pub fn resolve_export<S: MemorySource + ?Sized>(
+    source: &S,
+    module: Rc<Module>,
+    name: &str,
+) -> Option<(Rc<Module>, Rva)> {
+    let mut module = module;
+    let mut name = name.to_string();
+    for _ in 0..MAX_FORWARDS {
+        let export = match module.pe().and_then(|pe| pe.export(&name)) {
+            Some(export) => export.clone(),
+            None => {
+                debug!("Export {} not found in {}", name, module.name);
+                return None;
+            }
+        };
+
+        let forwarder = match export.forwarder {
+            Some(forwarder) => forwarder,
+            None => return Some((module, Rva(export.rva))),
+        };
+
+        // Forwarders look like `module.function` or `module.#ordinal`.
+        let (target, function) = match forwarder.rfind('.') {
+            Some(i) => (&forwarder[..i], &forwarder[i + 1..]),
+            None => {
+                warn!("Invalid forwarder {} for {}", forwarder, name);
+                return None;
+            }
+        };
+        let target = if target.contains('.') {
+            target.to_string()
+        } else {
+            format!("{}.dll", target)
+        };
+        debug!(
+            "Export {} of {} forwards to {}",
+            name, module.name, forwarder
+        );
+
+        module = source.get_module(&target).or_else(|| {
+            warn!("Forwarded module {} not found", target);
+            None
+        })?;
+        name = function.to_string();
+    }
+
+    warn!("Too many forwarded exports for {}", name);
+    None
+}
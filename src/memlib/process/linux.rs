@@ -0,0 +1,370 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+extern crate libc;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::os::unix::fs::FileExt;
+use std::rc::Rc;
+
+use crate::helpers::file_name;
+use crate::memlib::*;
+
+/// Bytes read from a mapping to parse its image headers.
+const HEADER_SIZE: usize = 0x1000;
+
+/// A process on Linux: either a native game, or a Windows game running
+/// through Wine or Proton. PE images mapped by Wine are reported with their
+/// Windows module names, shared objects with their file names.
+#[derive(Debug)]
+pub struct Process {
+    // Process id.
+    pub id: u32,
+
+    // Process bitness, derived from the headers of the main executable.
+    pub is_wow64: bool,
+
+    // `/proc/<pid>/mem`, used if `process_vm_readv` is not available.
+    mem: Option<File>,
+
+    // List of modules.
+    modules: RefCell<HashMap<String, Rc<Module>>>,
+}
+
+/// An executable image mapped into the process.
+#[derive(Debug, Clone, PartialEq)]
+struct MappedImage {
+    info: ModuleInfo,
+    headers: ImageHeaders,
+    path: String,
+}
+
+/// A file mapping from `/proc/<pid>/maps`.
+#[derive(Debug, Clone, PartialEq)]
+struct Mapping {
+    start: usize,
+    end: usize,
+    offset: usize,
+    protection: Protection,
+    path: String,
+}
+
+/// Parse a line of `/proc/<pid>/maps`.
+fn parse_maps_line(line: &str) -> Option<Mapping> {
+    let mut parts = line.splitn(6, ' ');
+    let mut range = parts.next()?.splitn(2, '-');
+    let start = usize::from_str_radix(range.next()?, 16).ok()?;
+    let end = usize::from_str_radix(range.next()?, 16).ok()?;
+    let perms = parts.next()?.as_bytes();
+    let offset = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let _dev = parts.next()?;
+    let _inode = parts.next()?;
+    let path = parts.next().unwrap_or("").trim().to_string();
+
+    Some(Mapping {
+        start,
+        end,
+        offset,
+        protection: Protection {
+            read: perms.first() == Some(&b'r'),
+            write: perms.get(1) == Some(&b'w'),
+            execute: perms.get(2) == Some(&b'x'),
+        },
+        path,
+    })
+}
+
+impl Process {
+    /// Read memory with `process_vm_readv`.
+    fn read_vm(&self, address: usize, buf: &mut [u8]) -> Option<usize> {
+        let local = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let remote = libc::iovec {
+            iov_base: address as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+
+        let n = unsafe { libc::process_vm_readv(self.id as libc::pid_t, &local, 1, &remote, 1, 0) };
+        match n {
+            -1 => None,
+            n => Some(n as usize),
+        }
+    }
+
+    /// Read the current file mappings of the process.
+    fn mappings(&self) -> Vec<Mapping> {
+        self.all_mappings()
+            .into_iter()
+            .filter(|m| m.path.starts_with('/'))
+            .collect()
+    }
+
+    /// Read all current mappings of the process.
+    fn all_mappings(&self) -> Vec<Mapping> {
+        let maps = match fs::read_to_string(format!("/proc/{}/maps", self.id)) {
+            Ok(maps) => maps,
+            Err(e) => {
+                debug!("Could not read maps of PID {}: {}", self.id, e);
+                return vec![];
+            }
+        };
+
+        maps.lines().filter_map(parse_maps_line).collect()
+    }
+
+    /// Find all PE and ELF images mapped into the process.
+    fn images(&self) -> Vec<MappedImage> {
+        let mut res: Vec<MappedImage> = vec![];
+        for m in self.mappings().into_iter().filter(|m| m.offset == 0) {
+            if res.iter().any(|i| i.info.base == Va(m.start)) {
+                continue;
+            }
+
+            let mut header = vec![0u8; HEADER_SIZE.min(m.end - m.start)];
+            if !self.read_bytes(Va(m.start), &mut header) {
+                continue;
+            }
+
+            if let Some(headers) = ImageHeaders::parse(&header) {
+                trace!("Found image {} at {:#X}", m.path, m.start);
+                res.push(MappedImage {
+                    info: ModuleInfo {
+                        name: file_name(&m.path).to_string(),
+                        path: m.path.clone(),
+                        base: Va(m.start),
+                        size: headers.size_of_image(),
+                    },
+                    headers,
+                    path: m.path,
+                });
+            }
+        }
+        res
+    }
+}
+
+impl MemorySource for Process {
+    fn modules(&self) -> Vec<ModuleInfo> {
+        self.images().into_iter().map(|i| i.info).collect()
+    }
+
+    fn get_module(&self, name: &str) -> Option<Rc<Module>> {
+        let mut b = self.modules.borrow_mut();
+        if b.contains_key(name) {
+            return b.get(name).cloned();
+        }
+
+        // Module names are case-insensitive on Windows.
+        self.images()
+            .into_iter()
+            .find(|i| i.info.name.eq_ignore_ascii_case(name))
+            .and_then(|i| Module::from_source(&i.info.name, i.info.base, i.info.size, self))
+            .and_then(|m| b.insert(name.to_string(), Rc::new(m)));
+        b.get(name).cloned()
+    }
+
+    fn read_bytes(&self, address: Va, buf: &mut [u8]) -> bool {
+        if let Some(n) = self.read_vm(address.0, buf) {
+            return n == buf.len();
+        }
+
+        match self.mem {
+            Some(ref mem) => mem.read_exact_at(buf, address.0 as u64).is_ok(),
+            None => false,
+        }
+    }
+
+    fn pointer_width(&self) -> usize {
+        if self.is_wow64 {
+            4
+        } else {
+            8
+        }
+    }
+
+    fn query_regions(&self, address: Va, size: usize) -> Vec<Region> {
+        let address = address.0;
+        let end = address.saturating_add(size);
+        let mut res = vec![];
+        let mut pos = address;
+        for m in self
+            .all_mappings()
+            .into_iter()
+            .filter(|m| m.start < end && m.end > address)
+        {
+            // Holes between mappings are not mapped at all.
+            if m.start > pos {
+                res.push(Region {
+                    offset: pos - address,
+                    size: m.start - pos,
+                    protection: Some(Protection::default()),
+                    readable: false,
+                });
+            }
+
+            let start = m.start.max(pos);
+            let stop = m.end.min(end);
+            res.push(Region {
+                offset: start - address,
+                size: stop - start,
+                protection: Some(m.protection),
+                readable: m.protection.read,
+            });
+            pos = stop;
+        }
+
+        if !res.is_empty() && pos < end {
+            res.push(Region {
+                offset: pos - address,
+                size: end - pos,
+                protection: Some(Protection::default()),
+                readable: false,
+            });
+        }
+        res
+    }
+}
+
+/// Name of the process, preferring the Windows executable name under Wine.
+fn process_name(pid: u32) -> Option<String> {
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let argv0 = cmdline.split(|&c| c == 0).next().unwrap_or(&[]);
+    let argv0 = String::from_utf8_lossy(argv0);
+    if !argv0.is_empty() {
+        return Some(file_name(&argv0).to_string());
+    }
+
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim().to_string())
+}
+
+impl Process {
+    /// Check if the process is still running, zombies count as exited.
+    pub fn is_alive(&self) -> bool {
+        let stat = match fs::read_to_string(format!("/proc/{}/stat", self.id)) {
+            Ok(stat) => stat,
+            Err(_) => return false,
+        };
+        // `pid (comm) state ...`, `comm` may contain spaces and parentheses.
+        let state = stat
+            .rfind(')')
+            .and_then(|i| stat[i + 1..].trim_start().chars().next());
+        !matches!(state, Some('Z') | Some('X') | None)
+    }
+
+    /// Derive the bitness from the main image of the process.
+    ///
+    /// Prefers the Windows executable under Wine, then the native executable.
+    fn detect_wow64(&self) -> Option<bool> {
+        let images = self.images();
+        let exe = fs::read_link(format!("/proc/{}/exe", self.id))
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let main = images
+            .iter()
+            .find(|i| i.info.name.to_lowercase().ends_with(".exe"))
+            .or_else(|| images.iter().find(|i| i.path == exe))
+            .or_else(|| images.first())?;
+        debug!("PID {} main image {}", self.id, main.info.name);
+        Some(main.headers.pointer_width() == 4)
+    }
+}
+
+pub fn from_pid(pid: u32) -> Option<Process> {
+    if !fs::metadata(format!("/proc/{}", pid)).is_ok_and(|m| m.is_dir()) {
+        return None;
+    }
+
+    let mut process = Process {
+        id: pid,
+        is_wow64: false,
+        mem: File::open(format!("/proc/{}/mem", pid)).ok(),
+        modules: RefCell::new(HashMap::new()),
+    };
+
+    match process.detect_wow64() {
+        Some(is_wow64) => {
+            process.is_wow64 = is_wow64;
+            debug!("PID {} is_wow64: {}", pid, is_wow64);
+        }
+        None => warn!(
+            "Could not determine process bitness: no image mapped in PID {}",
+            pid
+        ),
+    }
+
+    Some(process)
+}
+
+/// List all running processes.
+pub fn processes() -> Vec<ProcessInfo> {
+    processes_matching(|_| true)
+}
+
+/// List the running processes whose executable name passes `filter`.
+///
+/// Only those are probed for their bitness.
+pub fn processes_matching<F: Fn(&str) -> bool>(filter: F) -> Vec<ProcessInfo> {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Could not list processes: {}", e);
+            return vec![];
+        }
+    };
+
+    let mut res = vec![];
+    for entry in entries.filter_map(|e| e.ok()) {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        let name = match process_name(pid) {
+            Some(name) if filter(&name) => name,
+            _ => continue,
+        };
+
+        let process = Process {
+            id: pid,
+            is_wow64: false,
+            mem: None,
+            modules: RefCell::new(HashMap::new()),
+        };
+        res.push(ProcessInfo {
+            pid,
+            name,
+            is_wow64: process.detect_wow64(),
+        });
+    }
+
+    res.sort_by_key(|p| p.pid);
+    res
+}
@@ -0,0 +1,140 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+pub use self::linux::*;
+#[cfg(windows)]
+pub use self::windows::*;
+
+extern crate regex;
+
+use self::regex::Regex;
+use std::fmt;
+
+pub type Result<T> = ::std::result::Result<T, ProcessError>;
+
+#[derive(Debug, Fail)]
+pub enum ProcessError {
+    #[fail(display = "No process matches {}", _0)]
+    NotFound(String),
+
+    #[fail(display = "Multiple processes match {}: {}", _0, _1)]
+    Ambiguous(String, ProcessList),
+
+    #[fail(display = "Could not open process {}", _0)]
+    OpenFailed(u32),
+
+    #[fail(display = "Invalid process regex: {}", _0)]
+    InvalidRegex(String),
+}
+
+/// A running process, as listed by `processes`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProcessInfo {
+    // Process id.
+    pub pid: u32,
+
+    // Executable name, e.g. `csgo.exe`.
+    pub name: String,
+
+    // Process bitness, `None` if the process could not be opened.
+    pub is_wow64: Option<bool>,
+}
+
+impl fmt::Display for ProcessInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (pid {})", self.name, self.pid)
+    }
+}
+
+/// Candidates of an ambiguous process match.
+#[derive(Debug)]
+pub struct ProcessList(pub Vec<ProcessInfo>);
+
+impl fmt::Display for ProcessList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = self.0.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        write!(f, "{}", list.join(", "))
+    }
+}
+
+/// How to select the target process by name.
+#[derive(Debug)]
+pub enum ProcessMatcher {
+    /// Case-insensitive comparison with the whole executable name.
+    Exact(String),
+
+    /// Regex search in the executable name.
+    Regex(Regex),
+}
+
+impl ProcessMatcher {
+    /// Create a matcher from a regex.
+    pub fn regex(raw: &str) -> Result<Self> {
+        Regex::new(raw)
+            .map(ProcessMatcher::Regex)
+            .map_err(|e| ProcessError::InvalidRegex(e.to_string()))
+    }
+
+    /// Check if the executable `name` matches.
+    pub fn is_match(&self, name: &str) -> bool {
+        match self {
+            ProcessMatcher::Exact(s) => s.eq_ignore_ascii_case(name),
+            ProcessMatcher::Regex(r) => r.is_match(name),
+        }
+    }
+}
+
+impl fmt::Display for ProcessMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcessMatcher::Exact(s) => write!(f, "{}", s),
+            ProcessMatcher::Regex(r) => write!(f, "/{}/", r),
+        }
+    }
+}
+
+/// Find the single process matching `matcher` and open it.
+///
+/// Fails if no process or more than one process matches.
+pub fn find_process(matcher: &ProcessMatcher) -> Result<Process> {
+    let mut candidates = processes_matching(|name| matcher.is_match(name));
+
+    match candidates.len() {
+        0 => Err(ProcessError::NotFound(matcher.to_string())),
+        1 => open_pid(candidates.remove(0).pid),
+        _ => Err(ProcessError::Ambiguous(
+            matcher.to_string(),
+            ProcessList(candidates),
+        )),
+    }
+}
+
+/// Open the process with id `pid`.
+pub fn open_pid(pid: u32) -> Result<Process> {
+    from_pid(pid).ok_or(ProcessError::OpenFailed(pid))
+}
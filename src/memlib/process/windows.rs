@@ -0,0 +1,403 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+extern crate winapi;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::{mem, ptr};
+
+use self::winapi::shared::basetsd::SIZE_T;
+use self::winapi::shared::minwindef::{BOOL, DWORD, FALSE, LPCVOID, LPVOID, PBOOL};
+use self::winapi::shared::ntdef::HANDLE;
+use self::winapi::um::handleapi::CloseHandle;
+use self::winapi::um::memoryapi::{ReadProcessMemory, VirtualQueryEx, WriteProcessMemory};
+use self::winapi::um::minwinbase::STILL_ACTIVE;
+use self::winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess};
+use self::winapi::um::tlhelp32::{
+    Module32FirstW,
+    Module32NextW,
+    Process32FirstW,
+    Process32NextW,
+    MODULEENTRY32W,
+    PROCESSENTRY32W,
+    TH32CS_SNAPMODULE,
+    TH32CS_SNAPMODULE32,
+    TH32CS_SNAPPROCESS,
+};
+use self::winapi::um::winnt::{
+    MEMORY_BASIC_INFORMATION,
+    MEM_COMMIT,
+    PAGE_EXECUTE,
+    PAGE_EXECUTE_READ,
+    PAGE_EXECUTE_READWRITE,
+    PAGE_EXECUTE_WRITECOPY,
+    PAGE_GUARD,
+    PAGE_NOACCESS,
+    PAGE_READONLY,
+    PAGE_READWRITE,
+    PAGE_WRITECOPY,
+    PROCESS_ALL_ACCESS,
+    PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use self::winapi::um::wow64apiset::IsWow64Process;
+use crate::memlib::*;
+
+impl Constructor for PROCESSENTRY32W {
+    /// Create a new instance of `PROCESSENTRY32W`
+    fn new() -> Self {
+        let mut pe: PROCESSENTRY32W = unsafe { mem::zeroed() };
+        pe.dwSize = mem::size_of::<PROCESSENTRY32W>() as u32;
+        pe
+    }
+}
+
+impl Constructor for MODULEENTRY32W {
+    /// Create a new instance of `MODULEENTRY32W`
+    fn new() -> Self {
+        let mut module: MODULEENTRY32W = unsafe { mem::zeroed() };
+        module.dwSize = mem::size_of::<MODULEENTRY32W>() as u32;
+        module
+    }
+}
+
+#[derive(Debug)]
+pub struct Process {
+    // Process id.
+    pub id: u32,
+
+    // Process bitness.
+    pub is_wow64: bool,
+
+    // Process `HANDLE`.
+    handle: HANDLE,
+
+    // List of modules.
+    modules: RefCell<HashMap<String, Rc<Module>>>,
+}
+
+impl Process {
+    pub fn read_ptr<T: Copy>(&self, buf: *mut T, address: usize, count: usize) -> bool {
+        unsafe {
+            ReadProcessMemory(
+                self.handle,
+                address as LPCVOID,
+                buf as *mut T as LPVOID,
+                mem::size_of::<T>() as SIZE_T * count,
+                ptr::null_mut::<SIZE_T>(),
+            ) != FALSE
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn write<T: Copy>(&self, address: u32, buf: &T) -> bool {
+        unsafe {
+            WriteProcessMemory(
+                self.handle,
+                address as LPVOID,
+                buf as *const T as LPCVOID,
+                mem::size_of::<T>() as SIZE_T,
+                ptr::null_mut::<SIZE_T>(),
+            ) != FALSE
+        }
+    }
+}
+
+impl Process {
+    /// Check if the process is still running.
+    pub fn is_alive(&self) -> bool {
+        let mut code: DWORD = 0;
+        unsafe { GetExitCodeProcess(self.handle, &mut code) != FALSE && code == STILL_ACTIVE }
+    }
+
+    /// Walk the Toolhelp module snapshot and call `f` for every entry.
+    ///
+    /// Stops early and returns the value if `f` returns `Some`.
+    fn walk_modules<T, F>(&self, mut f: F) -> Option<T>
+    where
+        F: FnMut(&MODULEENTRY32W, String) -> Option<T>,
+    {
+        let snapshot = SnapshotHandle::new(self.id, TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32)?;
+        let mut me = MODULEENTRY32W::new();
+
+        if !module32_first(&snapshot, &mut me) {
+            return None;
+        }
+
+        loop {
+            let s = from_wide(&me.szModule);
+
+            if let Some(r) = f(&me, s) {
+                return Some(r);
+            }
+
+            if !module32_next(&snapshot, &mut me) {
+                break;
+            }
+        }
+
+        None
+    }
+}
+
+/// Convert a NUL-terminated UTF-16 buffer, ignoring anything after the
+/// first NUL that a reused buffer may still hold.
+fn from_wide(buf: &[u16]) -> String {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    String::from_utf16_lossy(&buf[..len])
+}
+
+/// Convert the `Protect` of a `MEMORY_BASIC_INFORMATION` to a `Protection`.
+fn protection(protect: u32) -> Protection {
+    let access = protect & 0xFF;
+    Protection {
+        read: access
+            & (PAGE_READONLY
+                | PAGE_READWRITE
+                | PAGE_WRITECOPY
+                | PAGE_EXECUTE_READ
+                | PAGE_EXECUTE_READWRITE
+                | PAGE_EXECUTE_WRITECOPY)
+            != 0,
+        write: access
+            & (PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY)
+            != 0,
+        execute: access
+            & (PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY)
+            != 0,
+    }
+}
+
+impl MemorySource for Process {
+    fn modules(&self) -> Vec<ModuleInfo> {
+        let mut res = vec![];
+        self.walk_modules::<(), _>(|me, name| {
+            res.push(ModuleInfo {
+                name,
+                path: from_wide(&me.szExePath),
+                base: Va(me.modBaseAddr as usize),
+                size: me.modBaseSize as usize,
+            });
+            None
+        });
+        res
+    }
+
+    fn get_module(&self, name: &str) -> Option<Rc<Module>> {
+        let mut b = self.modules.borrow_mut();
+        if b.contains_key(name) {
+            return b.get(name).cloned();
+        }
+
+        self.walk_modules(|me, s| {
+            if !name.eq_ignore_ascii_case(&s) {
+                return None;
+            }
+            Module::from_source(
+                &s,
+                Va(me.modBaseAddr as usize),
+                me.modBaseSize as usize,
+                self,
+            )
+        })
+        .and_then(|m| b.insert(name.to_string(), Rc::new(m)));
+        b.get(name).cloned()
+    }
+
+    fn read_bytes(&self, address: Va, buf: &mut [u8]) -> bool {
+        self.read_ptr(buf.as_mut_ptr(), address.0, buf.len())
+    }
+
+    fn pointer_width(&self) -> usize {
+        if self.is_wow64 {
+            4
+        } else {
+            mem::size_of::<usize>()
+        }
+    }
+
+    fn query_regions(&self, address: Va, size: usize) -> Vec<Region> {
+        let address = address.0;
+        let end = address.saturating_add(size);
+        let mut res = vec![];
+        let mut pos = address;
+        while pos < end {
+            let mut mbi: MEMORY_BASIC_INFORMATION = unsafe { mem::zeroed() };
+            let n = unsafe {
+                VirtualQueryEx(
+                    self.handle,
+                    pos as LPCVOID,
+                    &mut mbi,
+                    mem::size_of::<MEMORY_BASIC_INFORMATION>() as SIZE_T,
+                )
+            };
+            if n == 0 {
+                warn!("VirtualQueryEx failed at {:#X}", pos);
+                break;
+            }
+
+            let stop = (mbi.BaseAddress as usize + mbi.RegionSize as usize).min(end);
+            if stop <= pos {
+                break;
+            }
+
+            // Guard pages fault on access, reserved and free pages are empty.
+            let committed = mbi.State == MEM_COMMIT;
+            let protection = if committed {
+                protection(mbi.Protect)
+            } else {
+                Protection::default()
+            };
+            let readable =
+                committed && protection.read && mbi.Protect & (PAGE_GUARD | PAGE_NOACCESS) == 0;
+            res.push(Region {
+                offset: pos - address,
+                size: stop - pos,
+                protection: Some(protection),
+                readable,
+            });
+            pos = stop;
+        }
+
+        if !res.is_empty() && pos < end {
+            res.push(Region {
+                offset: pos - address,
+                size: end - pos,
+                protection: None,
+                readable: false,
+            });
+        }
+        res
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        if !self.handle.is_null() {
+            unsafe { CloseHandle(self.handle) };
+        }
+    }
+}
+
+/// Query the bitness of the process behind `handle`.
+fn is_wow64(handle: HANDLE) -> Option<bool> {
+    let mut tmp: BOOL = 0;
+
+    if unsafe { IsWow64Process(handle, &mut tmp as PBOOL) } == FALSE {
+        return None;
+    }
+
+    Some(tmp != FALSE)
+}
+
+pub fn from_pid(pid: u32) -> Option<Process> {
+    let handle = unsafe { OpenProcess(PROCESS_ALL_ACCESS, 0, pid) };
+    if handle.is_null() {
+        return None;
+    }
+
+    let is_wow64 = match is_wow64(handle) {
+        Some(is_wow64) => is_wow64,
+        None => {
+            warn!("Could not determine process bitness: IsWow64Process returned an error!");
+            unsafe { CloseHandle(handle) };
+            return None;
+        }
+    };
+    debug!("PID {} is_wow64: {}", pid, is_wow64);
+
+    Some(Process {
+        id: pid,
+        is_wow64,
+        handle,
+        modules: RefCell::new(HashMap::new()),
+    })
+}
+
+/// Wrapper around the `Module32FirstW` windows api
+fn module32_first(h: &SnapshotHandle, me: &mut MODULEENTRY32W) -> bool {
+    unsafe { Module32FirstW(**h, me) != FALSE }
+}
+
+/// Wrapper around the `Module32NextW` windows api
+fn module32_next(h: &SnapshotHandle, me: &mut MODULEENTRY32W) -> bool {
+    unsafe { Module32NextW(**h, me) != FALSE }
+}
+
+/// Wrapper around the `Process32FirstW` windows api
+fn process32_first(h: &SnapshotHandle, pe: &mut PROCESSENTRY32W) -> bool {
+    unsafe { Process32FirstW(**h, pe) != FALSE }
+}
+
+/// Wrapper around the `Process32NextW` windows api
+fn process32_next(h: &SnapshotHandle, pe: &mut PROCESSENTRY32W) -> bool {
+    unsafe { Process32NextW(**h, pe) != FALSE }
+}
+
+/// List all running processes.
+pub fn processes() -> Vec<ProcessInfo> {
+    processes_matching(|_| true)
+}
+
+/// List the running processes whose executable name passes `filter`.
+///
+/// Only those are opened to query their bitness.
+pub fn processes_matching<F: Fn(&str) -> bool>(filter: F) -> Vec<ProcessInfo> {
+    let mut res = vec![];
+    let snapshot = match SnapshotHandle::new(0, TH32CS_SNAPPROCESS) {
+        Some(snapshot) => snapshot,
+        None => return res,
+    };
+    let mut pe = PROCESSENTRY32W::new();
+
+    if !process32_first(&snapshot, &mut pe) {
+        return res;
+    }
+
+    loop {
+        let name = from_wide(&pe.szExeFile);
+        if filter(&name) {
+            let handle =
+                unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pe.th32ProcessID) };
+            let is_wow64 = if handle.is_null() {
+                None
+            } else {
+                let res = is_wow64(handle);
+                unsafe { CloseHandle(handle) };
+                res
+            };
+
+            res.push(ProcessInfo {
+                pid: pe.th32ProcessID,
+                name,
+                is_wow64,
+            });
+        }
+
+        if !process32_next(&snapshot, &mut pe) {
+            break;
+        }
+    }
+
+    res
+}
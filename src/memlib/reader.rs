@@ -0,0 +1,256 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::memlib::*;
+use std::mem;
+
+/// Size of the pages terminated strings are read in.
+const PAGE_SIZE: usize = 0x1000;
+
+pub type ReadResult<T> = ::std::result::Result<T, ReadError>;
+
+#[derive(Debug, Fail, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError {
+    #[fail(display = "Could not read {:#X} bytes at {:#X}", size, address)]
+    Unreadable { address: usize, size: usize },
+
+    #[fail(display = "{:#X} bytes at {:#X} are out of bounds", size, address)]
+    OutOfBounds { address: usize, size: usize },
+
+    #[fail(display = "Invalid pointer width {} at {:#X}", width, address)]
+    InvalidWidth { address: usize, width: usize },
+
+    #[fail(
+        display = "No terminator within {} characters at {:#X}",
+        max_len, address
+    )]
+    Unterminated { address: usize, max_len: usize },
+}
+
+/// A plain value that is decoded from little-endian bytes.
+pub trait Primitive: Copy + Default + PartialEq {
+    /// Size in bytes.
+    const SIZE: usize;
+
+    /// Decode the value from exactly `SIZE` bytes.
+    fn from_le(raw: &[u8]) -> Self;
+}
+
+macro_rules! impl_primitive {
+    ($($t:ty),*) => {$(
+        impl Primitive for $t {
+            const SIZE: usize = mem::size_of::<$t>();
+
+            fn from_le(raw: &[u8]) -> Self {
+                let mut buf = [0u8; mem::size_of::<$t>()];
+                buf.copy_from_slice(raw);
+                <$t>::from_le_bytes(buf)
+            }
+        }
+    )*};
+}
+
+impl_primitive!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+/// Read a little-endian value at `offset` of a byte slice.
+pub fn read_le<T: Primitive>(data: &[u8], offset: usize) -> Option<T> {
+    let raw = data.get(offset..offset.checked_add(T::SIZE)?)?;
+    Some(T::from_le(raw))
+}
+
+/// Read a little-endian word of `width` bytes at `offset` of a byte slice.
+pub fn read_word(data: &[u8], offset: usize, width: usize) -> Option<u64> {
+    match width {
+        1 => read_le::<u8>(data, offset).map(u64::from),
+        2 => read_le::<u16>(data, offset).map(u64::from),
+        4 => read_le::<u32>(data, offset).map(u64::from),
+        8 => read_le::<u64>(data, offset),
+        _ => None,
+    }
+}
+
+/// Typed reads on top of a byte reader.
+///
+/// All reads are bounds checked and decoded from bytes, so a bad address
+/// results in an error carrying that address.
+pub trait MemoryReader {
+    /// Fill `buf` with the bytes at `address`.
+    fn read_into(&self, address: Va, buf: &mut [u8]) -> ReadResult<()>;
+
+    /// Read a little-endian value.
+    fn read<T: Primitive>(&self, address: Va) -> ReadResult<T> {
+        let mut buf = [0u8; 8];
+        let buf = &mut buf[..T::SIZE];
+        self.read_into(address, buf)?;
+        Ok(T::from_le(buf))
+    }
+
+    /// Read a pointer of `width` bytes.
+    fn read_pointer(&self, address: Va, width: usize) -> ReadResult<Va> {
+        match width {
+            4 => self.read::<u32>(address).map(|v| Va(v as usize)),
+            8 => self.read::<u64>(address).map(|v| Va(v as usize)),
+            _ => Err(ReadError::InvalidWidth {
+                address: address.0,
+                width,
+            }),
+        }
+    }
+
+    /// Read `N` consecutive little-endian values.
+    fn read_array<T: Primitive, const N: usize>(&self, address: Va) -> ReadResult<[T; N]> {
+        let mut buf = vec![0u8; T::SIZE * N];
+        self.read_into(address, &mut buf)?;
+
+        let mut res = [T::default(); N];
+        for (v, raw) in res.iter_mut().zip(buf.chunks_exact(T::SIZE)) {
+            *v = T::from_le(raw);
+        }
+        Ok(res)
+    }
+
+    /// Read a NUL terminated string of at most `max_len` bytes.
+    fn read_cstring(&self, address: Va, max_len: usize) -> ReadResult<String> {
+        let raw = self.read_terminated::<u8>(address, max_len)?;
+        Ok(String::from_utf8_lossy(&raw).into_owned())
+    }
+
+    /// Read a NUL terminated UTF-16 string of at most `max_len` characters.
+    #[allow(dead_code)]
+    fn read_utf16_string(&self, address: Va, max_len: usize) -> ReadResult<String> {
+        let raw = self.read_terminated::<u16>(address, max_len)?;
+        Ok(String::from_utf16_lossy(&raw))
+    }
+
+    /// Read values up to a zero value, at most `max_len` of them.
+    ///
+    /// Reads page by page, so the string may end right before unreadable
+    /// memory.
+    fn read_terminated<T: Primitive>(&self, address: Va, max_len: usize) -> ReadResult<Vec<T>> {
+        let mut res = vec![];
+        let mut pos = address;
+        while res.len() < max_len {
+            let count = ((PAGE_SIZE - pos.0 % PAGE_SIZE) / T::SIZE)
+                .max(1)
+                .min(max_len - res.len());
+            let mut buf = vec![0u8; count * T::SIZE];
+            self.read_into(pos, &mut buf)?;
+
+            for raw in buf.chunks_exact(T::SIZE) {
+                let v = T::from_le(raw);
+                if v == T::default() {
+                    return Ok(res);
+                }
+                res.push(v);
+            }
+            pos = pos + buf.len();
+        }
+        Err(ReadError::Unterminated {
+            address: address.0,
+            max_len,
+        })
+    }
+}
+
+impl<S: MemorySource + ?Sized> MemoryReader for S {
+    fn read_into(&self, address: Va, buf: &mut [u8]) -> ReadResult<()> {
+        if self.read_bytes(address, buf) {
+            Ok(())
+        } else {
+            Err(ReadError::Unreadable {
+                address: address.0,
+                size: buf.len(),
+            })
+        }
+    }
+}
+
+/// Reads from the data of a module mapped at its base.
+impl MemoryReader for Module {
+    fn read_into(&self, address: Va, buf: &mut [u8]) -> ReadResult<()> {
+        let size = buf.len();
+        let rva = match self.rva(address) {
+            Some(rva) if size <= self.size - rva.0 => rva,
+            _ => {
+                return Err(ReadError::OutOfBounds {
+                    address: address.0,
+                    size,
+                })
+            }
+        };
+        if !self.is_readable(rva, size) {
+            return Err(ReadError::Unreadable {
+                address: address.0,
+                size,
+            });
+        }
+
+        buf.copy_from_slice(&self.data[rva.0..rva.0 + size]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Va = Va(0x1000_0000);
+
+    #[test]
+    fn reads_slices() {
+        let data = [0x11, 0x22, 0x33, 0x44, 0x55];
+        assert_eq!(read_le::<u32>(&data, 1), Some(0x5544_3322));
+        assert_eq!(read_le::<u32>(&data, 2), None);
+        assert_eq!(read_le::<u32>(&data, usize::MAX), None);
+        assert_eq!(read_word(&data, 0, 2), Some(0x2211));
+        assert_eq!(read_word(&data, 0, 3), None);
+        assert_eq!(read_word(&data, 0, 8), None);
+    }
+
+    #[test]
+    fn reads_strings() {
+        let mut data = b"abc\0".to_vec();
+        data.extend("\u{e9}t\u{e9}\0".encode_utf16().flat_map(u16::to_le_bytes));
+        data.resize(PAGE_SIZE, b'x');
+        let module = Module::new("test.dll", BASE, data);
+
+        assert_eq!(module.read_cstring(BASE, 8).unwrap(), "abc");
+        assert_eq!(
+            module.read_utf16_string(BASE + 4, 8).unwrap(),
+            "\u{e9}t\u{e9}"
+        );
+        assert_eq!(
+            module.read_cstring(BASE + (PAGE_SIZE - 4), 8),
+            Err(ReadError::OutOfBounds {
+                address: BASE.0 + PAGE_SIZE,
+                size: 4
+            })
+        );
+        assert_eq!(
+            module.read_cstring(BASE + 16, 2),
+            Err(ReadError::Unterminated {
+                address: BASE.0 + 16,
+                max_len: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,169 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use memchr::memmem::Finder;
+use std::collections::HashMap;
+
+use crate::memlib::*;
+
+/// Prefixes of MSVC class and struct type descriptor names.
+const TYPE_PREFIXES: [&[u8]; 2] = [b".?AV", b".?AU"];
+
+/// `CompleteObjectLocator` signature of 32-bit images, which use VAs.
+const COL_SIGNATURE32: u32 = 0;
+
+/// `CompleteObjectLocator` signature of 64-bit images, which use RVAs.
+const COL_SIGNATURE64: u32 = 1;
+
+/// A class vtable found through MSVC RTTI.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VTable {
+    // Demangled class name, e.g. `C_CSPlayer`.
+    pub class: String,
+
+    // Type descriptor name, e.g. `.?AVC_CSPlayer@@`.
+    pub mangled: String,
+
+    // RVA of the first virtual function pointer.
+    pub rva: Rva,
+
+    // Offset of the subobject using this vtable, 0 for the primary vtable.
+    pub offset: u32,
+}
+
+/// Demangle a type descriptor name such as `.?AVCFoo@ns@@` to `ns::CFoo`.
+pub fn demangle_type_name(mangled: &str) -> String {
+    let name = mangled
+        .strip_prefix(".?AV")
+        .or_else(|| mangled.strip_prefix(".?AU"))
+        .unwrap_or(mangled);
+    let name = name.strip_suffix("@@").unwrap_or(name);
+
+    // Templates keep their mangled form.
+    if name.starts_with("?$") {
+        return name.to_string();
+    }
+    name.split('@').rev().collect::<Vec<_>>().join("::")
+}
+
+/// Find all type descriptors, keyed by the value a locator uses to refer
+/// to them: their VA in 32-bit images and their RVA in 64-bit images.
+fn find_type_descriptors(module: &Module, pointer_width: usize) -> HashMap<u32, String> {
+    let data = &module.data;
+    let mut res = HashMap::new();
+    for prefix in &TYPE_PREFIXES {
+        for name_offset in Finder::new(prefix).find_iter(data) {
+            // `TypeDescriptor {pVFTable, spare, name}`
+            let td = match name_offset.checked_sub(2 * pointer_width) {
+                Some(td) => td,
+                None => continue,
+            };
+            let name = match crate::helpers::parse_string(&data[name_offset..]) {
+                Ok((_, name)) => name.to_string(),
+                Err(_) => continue,
+            };
+
+            let key = if pointer_width == 4 {
+                module.va(Rva(td)).0 as u32
+            } else {
+                td as u32
+            };
+            res.insert(key, name);
+        }
+    }
+    res
+}
+
+/// Find all complete object locators, mapped to their VA.
+///
+/// Returns the type descriptor name and subobject offset of every locator.
+fn find_locators(
+    module: &Module,
+    pointer_width: usize,
+    types: &HashMap<u32, String>,
+) -> HashMap<Va, (String, u32)> {
+    let data = &module.data;
+    let mut res = HashMap::new();
+    for col in (0..data.len().saturating_sub(24)).step_by(4) {
+        let signature = match read_le::<u32>(data, col) {
+            Some(s) => s,
+            None => break,
+        };
+        let valid = match (pointer_width, signature) {
+            (4, COL_SIGNATURE32) => true,
+            // 64-bit locators point to themselves.
+            (8, COL_SIGNATURE64) => read_le::<u32>(data, col + 20) == Some(col as u32),
+            _ => false,
+        };
+        if !valid {
+            continue;
+        }
+
+        let name = match read_le::<u32>(data, col + 12).and_then(|td| types.get(&td)) {
+            Some(name) => name,
+            None => continue,
+        };
+        let offset = read_le::<u32>(data, col + 4).unwrap_or(0);
+        res.insert(module.va(Rva(col)), (name.clone(), offset));
+    }
+    res
+}
+
+/// Find all class vtables of a module through MSVC RTTI.
+///
+/// A vtable is preceded by a pointer to its `CompleteObjectLocator`, which
+/// leads to the `TypeDescriptor` with the class name.
+pub fn find_vtables(module: &Module, pointer_width: usize) -> Vec<VTable> {
+    let types = find_type_descriptors(module, pointer_width);
+    debug!(
+        "Found {} RTTI type descriptors in {}",
+        types.len(),
+        module.name
+    );
+    if types.is_empty() {
+        return vec![];
+    }
+
+    let locators = find_locators(module, pointer_width, &types);
+    debug!("Found {} RTTI locators in {}", locators.len(), module.name);
+
+    let data = &module.data;
+    let mut res = vec![];
+    for slot in (0..data.len().saturating_sub(pointer_width)).step_by(pointer_width) {
+        let mut raw = [0u8; 8];
+        raw[..pointer_width].copy_from_slice(&data[slot..slot + pointer_width]);
+        let value = Va(u64::from_le_bytes(raw) as usize);
+
+        if let Some((mangled, offset)) = locators.get(&value) {
+            res.push(VTable {
+                class: demangle_type_name(mangled),
+                mangled: mangled.clone(),
+                rva: Rva(slot + pointer_width),
+                offset: *offset,
+            });
+        }
+    }
+
+    res.sort_by(|a, b| a.class.cmp(&b.class).then(a.offset.cmp(&b.offset)));
+    debug!("Found {} vtables in {}", res.len(), module.name);
+    res
+}
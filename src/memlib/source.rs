@@ -0,0 +1,76 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::memlib::{Module, Region, Va};
+use std::rc::Rc;
+
+/// Basic information about a module known to a `MemorySource`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModuleInfo {
+    // Module name, e.g. `client.dll`.
+    pub name: String,
+
+    // Full path of the module file, empty if unknown.
+    pub path: String,
+
+    // Module base address.
+    pub base: Va,
+
+    // Module size in bytes.
+    pub size: usize,
+}
+
+impl ModuleInfo {
+    /// Check if `address` lies inside the module.
+    pub fn contains(&self, address: Va) -> bool {
+        address
+            .0
+            .checked_sub(self.base.0)
+            .is_some_and(|offset| offset < self.size)
+    }
+}
+
+/// Something that modules and memory can be read from.
+///
+/// The Windows `Process` is one implementation. Signature scanning and the
+/// netvar walker only depend on this trait, so other backends can plug in.
+pub trait MemorySource {
+    /// Enumerate all modules.
+    fn modules(&self) -> Vec<ModuleInfo>;
+
+    /// Get a module by name.
+    fn get_module(&self, name: &str) -> Option<Rc<Module>>;
+
+    /// Read `buf.len()` bytes starting at `address`.
+    fn read_bytes(&self, address: Va, buf: &mut [u8]) -> bool;
+
+    /// Pointer width of the target in bytes.
+    fn pointer_width(&self) -> usize;
+
+    /// Describe the memory layout of `size` bytes at `address`.
+    ///
+    /// Offsets are relative to `address` and `readable` marks regions that
+    /// are expected to be readable. Empty if the backend does not know.
+    fn query_regions(&self, _address: Va, _size: usize) -> Vec<Region> {
+        vec![]
+    }
+}
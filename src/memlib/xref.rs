@@ -0,0 +1,224 @@
+// MIT License
+//
+// Copyright (c) 2018 frk <hazefrk+dev@gmail.com>
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::memlib::*;
+use memchr::memmem::Finder;
+
+/// `IMAGE_DIRECTORY_ENTRY_EXCEPTION`
+pub const DIRECTORY_EXCEPTION: usize = 3;
+
+/// `UNW_FLAG_CHAININFO`
+const UNW_FLAG_CHAININFO: u8 = 4;
+
+/// Size of a `RUNTIME_FUNCTION`.
+const RUNTIME_FUNCTION_SIZE: usize = 12;
+
+/// Upper bound for walking back to a function start on x86.
+const MAX_FUNCTION_SIZE: usize = 0x4000;
+
+/// Alignment of function starts emitted by MSVC on x86.
+const FUNCTION_ALIGNMENT: usize = 0x10;
+
+/// Ranges of the code or the data sections of a module.
+///
+/// Falls back to the whole module if it is not a PE image.
+fn section_ranges(module: &Module, code: bool) -> Vec<(usize, usize)> {
+    match module.pe() {
+        Some(pe) => pe
+            .sections
+            .iter()
+            .filter(|s| s.is_code() == code)
+            .map(|s| {
+                let end = (s.virtual_address + s.mapped_size()).min(module.data.len());
+                (s.virtual_address.min(end), end)
+            })
+            .collect(),
+        None => vec![(0, module.data.len())],
+    }
+}
+
+/// Find the string literal `s` in the data sections of a module.
+///
+/// The literal must be NUL-terminated and start after a NUL, so that
+/// suffixes of longer strings do not match. Returns its RVA.
+pub fn find_string(module: &Module, s: &str, wide: bool) -> Option<Rva> {
+    let (mut needle, unit) = if wide {
+        let raw = s
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>();
+        (raw, 2)
+    } else {
+        (s.as_bytes().to_vec(), 1)
+    };
+    needle.resize(needle.len() + unit, 0);
+
+    let finder = Finder::new(&needle);
+    for (start, end) in section_ranges(module, false) {
+        let data = &module.data[start..end];
+
+        // Candidates may overlap, resume right after a rejected one.
+        let mut pos = 0;
+        while let Some(i) = finder.find(&data[pos..]).map(|i| i + pos) {
+            let aligned = !wide || i % 2 == 0;
+            if aligned && (i < unit || data[i - unit..i].iter().all(|&b| b == 0)) {
+                return Some(Rva(start + i));
+            }
+            pos = i + 1;
+        }
+    }
+    None
+}
+
+/// Find the first instruction in the code sections referencing `target`.
+///
+/// Recognizes `push imm32`, `mov r32, imm32`, `mov r/m32, imm32` and
+/// `lea r32, [disp32]` on x86 and RIP-relative `lea` on x64. Returns the RVA
+/// of the instruction.
+pub fn find_xref(module: &Module, target: Rva, pointer_width: usize) -> Option<Rva> {
+    let data = &module.data;
+    let va = module.va(target).0 as u32;
+    for (start, end) in section_ranges(module, true) {
+        for i in start..end.saturating_sub(6) {
+            let c = &data[i..];
+            let found = if pointer_width == 4 {
+                match c[0] {
+                    0x68 | 0xB8..=0xBF => read_le::<u32>(data, i + 1) == Some(va),
+                    0x8D if c[1] & 0xC7 == 0x05 => read_le::<u32>(data, i + 2) == Some(va),
+                    0xC7 => {
+                        mov_imm32_offset(c).is_some_and(|o| read_le::<u32>(data, i + o) == Some(va))
+                    }
+                    _ => false,
+                }
+            } else if (c[0] == 0x48 || c[0] == 0x4C) && c[1] == 0x8D && c[2] & 0xC7 == 0x05 {
+                read_le::<u32>(data, i + 3).is_some_and(|rel| {
+                    (i + 7).wrapping_add(rel as i32 as isize as usize) == target.0
+                })
+            } else {
+                false
+            };
+
+            if found {
+                return Some(Rva(i));
+            }
+        }
+    }
+    None
+}
+
+/// Offset of the immediate of a `C7 /0 mov r/m32, imm32` at the start of
+/// `code`, `None` for other `C7` encodings.
+fn mov_imm32_offset(code: &[u8]) -> Option<usize> {
+    let modrm = *code.get(1)?;
+    let (md, reg, rm) = (modrm >> 6, modrm >> 3 & 7, modrm & 7);
+    if reg != 0 {
+        return None;
+    }
+
+    // Opcode and ModRM, then SIB and displacement.
+    let mut len = 2;
+    if md != 3 && rm == 4 {
+        let sib = *code.get(2)?;
+        len += 1;
+        if md == 0 && sib & 7 == 5 {
+            len += 4;
+        }
+    }
+    len += match md {
+        0 if rm == 5 => 4,
+        1 => 1,
+        2 => 4,
+        _ => 0,
+    };
+    Some(len)
+}
+
+/// Find the start of the function containing the code at `rva`.
+///
+/// Uses the `.pdata` unwind table of x64 images. On x86, walks back to the
+/// nearest aligned address preceded by `int3` padding.
+pub fn find_function_start(module: &Module, rva: Rva, pointer_width: usize) -> Option<Rva> {
+    if pointer_width == 8 {
+        find_runtime_function(module, rva.0).map(Rva)
+    } else {
+        find_padded_start(module, rva.0).map(Rva)
+    }
+}
+
+fn find_runtime_function(module: &Module, rva: usize) -> Option<usize> {
+    let data = &module.data;
+    let dir = PeHeaders::parse(data)?.data_directory(DIRECTORY_EXCEPTION)?;
+    let count = dir.size / RUNTIME_FUNCTION_SIZE;
+
+    // `RUNTIME_FUNCTION {BeginAddress, EndAddress, UnwindData}`, sorted.
+    let entry = |i: usize| -> Option<(usize, usize, usize)> {
+        let e = dir.rva + i * RUNTIME_FUNCTION_SIZE;
+        Some((
+            read_le::<u32>(data, e)? as usize,
+            read_le::<u32>(data, e + 4)? as usize,
+            read_le::<u32>(data, e + 8)? as usize,
+        ))
+    };
+
+    let (mut lo, mut hi) = (0, count);
+    let mut found = None;
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        let (begin, end, unwind) = entry(mid)?;
+        if rva < begin {
+            hi = mid;
+        } else if rva >= end {
+            lo = mid + 1;
+        } else {
+            found = Some((begin, unwind));
+            break;
+        }
+    }
+    let (mut begin, mut unwind) = found?;
+
+    // Chained unwind info belongs to a fragment, follow it to the primary.
+    for _ in 0..32 {
+        let header = *data.get(unwind)?;
+        if header >> 3 & UNW_FLAG_CHAININFO == 0 {
+            return Some(begin);
+        }
+        let codes = *data.get(unwind + 2)? as usize;
+        let chained = unwind + 4 + ((codes + 1) & !1) * 2;
+        begin = read_le::<u32>(data, chained)? as usize;
+        unwind = read_le::<u32>(data, chained + 8)? as usize;
+    }
+    None
+}
+
+fn find_padded_start(module: &Module, rva: usize) -> Option<usize> {
+    let data = &module.data;
+    let lowest = rva.saturating_sub(MAX_FUNCTION_SIZE);
+    let mut pos = rva - rva % FUNCTION_ALIGNMENT;
+    while pos > lowest {
+        let prev = *data.get(pos - 1)?;
+        if prev == 0xCC && *data.get(pos)? != 0xCC {
+            return Some(pos);
+        }
+        pos -= FUNCTION_ALIGNMENT;
+    }
+    None
+}
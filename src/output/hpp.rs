@@ -24,7 +24,7 @@ use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 
-use super::{Dumpable, Results};
+use super::{identifier, Dumpable, Results};
 
 pub struct Dumper<'a> {
     res: &'a Results,
@@ -38,6 +38,7 @@ impl<'a> Dumpable for Dumper<'a> {
         self.timestamp()?;
         self.netvars()?;
         self.signatures()?;
+        self.interfaces()?;
         writeln!(&mut self.file, "}} // namespace hazedumper")?;
         Ok(())
     }
@@ -88,6 +89,35 @@ impl<'a> Dumpable for Dumper<'a> {
         writeln!(&mut self.file, "}} // namespace signatures")?;
         Ok(())
     }
+
+    /// Write the interfaces.
+    fn interfaces(&mut self) -> io::Result<()> {
+        if let Some(ref interfaces) = self.res.interfaces {
+            writeln!(&mut self.file, "namespace interfaces {{")?;
+            for (module, list) in interfaces {
+                writeln!(&mut self.file, "namespace {} {{", identifier(module))?;
+                for (k, v) in list {
+                    writeln!(
+                        &mut self.file,
+                        "constexpr ::std::ptrdiff_t {}_create_fn = {:#X};",
+                        identifier(k),
+                        v.create_fn
+                    )?;
+                    if let Some(instance) = v.instance {
+                        writeln!(
+                            &mut self.file,
+                            "constexpr ::std::ptrdiff_t {} = {:#X};",
+                            identifier(k),
+                            instance
+                        )?;
+                    }
+                }
+                writeln!(&mut self.file, "}} // namespace {}", identifier(module))?;
+            }
+            writeln!(&mut self.file, "}} // namespace interfaces")?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a> Dumper<'a> {
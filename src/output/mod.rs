@@ -53,6 +53,23 @@ pub struct Results {
     // Will not be serialized if `None`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub netvars: Option<Map<isize>>,
+
+    // Optional interfaces per module.
+    // Will not be serialized if `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interfaces: Option<Map<Map<InterfaceOffsets>>>,
+}
+
+// This struct represents a registered interface of a module.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InterfaceOffsets {
+    // RVA of the factory function.
+    pub create_fn: usize,
+
+    // RVA of the instance, `None` if it could not be resolved statically.
+    // Will not be serialized if `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<usize>,
 }
 
 /// Trait to be implemented to be dumpable.
@@ -71,14 +88,29 @@ trait Dumpable {
 
     /// Write the signatures.
     fn signatures(&mut self) -> io::Result<()>;
+
+    /// Write the interfaces.
+    fn interfaces(&mut self) -> io::Result<()>;
+}
+
+/// Turn a module or interface name into a valid identifier.
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
 }
 
 impl Results {
-    pub fn new(signatures: Map<usize>, netvars: Option<Map<isize>>) -> Self {
+    pub fn new(
+        signatures: Map<usize>,
+        netvars: Option<Map<isize>>,
+        interfaces: Option<Map<Map<InterfaceOffsets>>>,
+    ) -> Self {
         Results {
             timestamp: Utc::now(),
             signatures,
             netvars,
+            interfaces,
         }
     }
 
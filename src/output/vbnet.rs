@@ -24,7 +24,7 @@ use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 
-use super::{Dumpable, Results};
+use super::{identifier, Dumpable, Results};
 
 pub struct Dumper<'a> {
     res: &'a Results,
@@ -38,6 +38,7 @@ impl<'a> Dumpable for Dumper<'a> {
         self.timestamp()?;
         self.netvars()?;
         self.signatures()?;
+        self.interfaces()?;
         writeln!(&mut self.file, "End Namespace")?;
         Ok(())
     }
@@ -86,6 +87,39 @@ impl<'a> Dumpable for Dumper<'a> {
         writeln!(&mut self.file, "    End Class")?;
         Ok(())
     }
+
+    /// Write the interfaces.
+    fn interfaces(&mut self) -> io::Result<()> {
+        if let Some(ref interfaces) = self.res.interfaces {
+            writeln!(&mut self.file, "    Public Shared Class interfaces")?;
+            for (module, list) in interfaces {
+                writeln!(
+                    &mut self.file,
+                    "        Public Shared Class {}",
+                    identifier(module)
+                )?;
+                for (k, v) in list {
+                    writeln!(
+                        &mut self.file,
+                        "            Public Const {}_create_fn as Integer = &H{:X}",
+                        identifier(k),
+                        v.create_fn
+                    )?;
+                    if let Some(instance) = v.instance {
+                        writeln!(
+                            &mut self.file,
+                            "            Public Const {} as Integer = &H{:X}",
+                            identifier(k),
+                            instance
+                        )?;
+                    }
+                }
+                writeln!(&mut self.file, "        End Class")?;
+            }
+            writeln!(&mut self.file, "    End Class")?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a> Dumper<'a> {
@@ -21,8 +21,25 @@
 // SOFTWARE.
 
 use crate::config::Signature;
-use crate::memlib::Process;
+use crate::memlib::{
+    find_function_start,
+    find_string,
+    find_xref,
+    resolve_export,
+    MemoryReader,
+    MemorySource,
+    Module,
+    Pattern,
+    PatternError,
+    PatternSet,
+    ReadError,
+    Rva,
+    Va,
+};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::mem;
+use std::rc::Rc;
 
 pub type Result<T> = ::std::result::Result<T, ScanError>;
 
@@ -34,54 +51,280 @@ pub enum ScanError {
     #[fail(display = "Pattern not found")]
     PatternNotFound,
 
-    #[fail(display = "Offset out of module bounds")]
-    OffsetOutOfBounds,
+    #[fail(display = "Pattern is not unique, {}", _0)]
+    AmbiguousPattern(Matches),
+
+    #[fail(display = "match_index {} out of range, {} matches", index, count)]
+    MatchIndexOutOfRange { index: usize, count: usize },
+
+    #[fail(display = "Invalid pattern: {}", _0)]
+    InvalidPattern(#[cause] PatternError),
 
     #[fail(display = "rip_relative failed")]
     RIPRelativeFailed,
+
+    #[fail(display = "Export not found")]
+    ExportNotFound,
+
+    #[fail(display = "VTable not found")]
+    VTableNotFound,
+
+    #[fail(display = "String not found")]
+    StringNotFound,
+
+    #[fail(display = "String reference not found")]
+    XrefNotFound,
+
+    #[fail(display = "Function start not found")]
+    FunctionStartNotFound,
+
+    #[fail(display = "{}", _0)]
+    Read(#[cause] ReadError),
+
+    #[fail(display = "Result {:#X} is outside of module {}", _0, _1)]
+    OutsideModule(Va, String),
+
+    #[fail(display = "Neither pattern, export, vtable nor string given")]
+    MissingPattern,
 }
 
-pub fn find_signature(sig: &Signature, process: &Process) -> Result<usize> {
-    debug!("Begin scan: {}", sig.name);
-    debug!("IsWow64: {:?}", process.is_wow64);
+/// Addresses of the matches of an ambiguous pattern.
+#[derive(Debug)]
+pub struct Matches(pub Vec<Va>);
+
+impl fmt::Display for Matches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} matches at ", self.0.len())?;
+        for (i, va) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:#X}", va)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pick the match of `sig` from all matches of its pattern.
+///
+/// Honors `match_index`, otherwise requires a unique match unless
+/// `expect_unique` is off, in which case the first one is used.
+fn select_match(sig: &Signature, module: &Module, matches: &[Rva]) -> Result<Rva> {
+    debug!("{}: {} pattern matches", sig.name, matches.len());
+    if let Some(index) = sig.match_index {
+        return matches
+            .get(index)
+            .cloned()
+            .ok_or(ScanError::MatchIndexOutOfRange {
+                index,
+                count: matches.len(),
+            });
+    }
+
+    match matches {
+        [] => Err(ScanError::PatternNotFound),
+        [rva] => Ok(*rva),
+        [rva, ..] if !sig.expect_unique => Ok(*rva),
+        _ => Err(ScanError::AmbiguousPattern(Matches(
+            matches.iter().map(|&rva| module.va(rva)).collect(),
+        ))),
+    }
+}
+
+/// Get the module of `sig` from `source`.
+fn load_module<S: MemorySource + ?Sized>(sig: &Signature, source: &S) -> Result<Rc<Module>> {
     debug!("Load module {}", sig.module);
-    let module = process
+    let module = source
         .get_module(&sig.module)
         .ok_or(ScanError::ModuleNotFound)?;
     debug!(
         "Module found: {} - Base: {:#X} Size: {:#X}",
         module.name, module.base, module.size
     );
+    if let Some(pe) = module.pe() {
+        debug!(
+            "Module build: TimeDateStamp {:#X} CheckSum {:#X}",
+            pe.timestamp, pe.checksum
+        );
+    }
+    Ok(module)
+}
 
-    debug!("Searching pattern: {}", sig.pattern);
-    let mut addr = module
-        .find_pattern(&sig.pattern)
-        .ok_or(ScanError::PatternNotFound)?;
-    debug!(
-        "Pattern found at: {:#X} (+ base = {:#X})",
-        addr,
-        addr + module.base
-    );
+/// Parse the pattern of `sig`, in code syntax if it has a mask.
+fn parse_pattern(sig: &Signature) -> Result<Pattern> {
+    match sig.mask {
+        Some(ref mask) => Pattern::parse_code(&sig.pattern, mask),
+        None => Pattern::parse(&sig.pattern),
+    }
+    .map_err(ScanError::InvalidPattern)
+}
+
+/// Check if `sig` is located by its pattern alone.
+fn is_pattern_only(sig: &Signature) -> bool {
+    sig.export.is_none() && sig.vtable.is_none() && sig.string.is_none() && !sig.pattern.is_empty()
+}
+
+pub fn find_signature<S: MemorySource + ?Sized>(sig: &Signature, source: &S) -> Result<usize> {
+    debug!("Begin scan: {}", sig.name);
+    debug!("Pointer width: {}", source.pointer_width());
+    let module = load_module(sig, source)?;
+
+    let (module, rva) = if let Some(ref export) = sig.export {
+        debug!("Resolving export: {}", export);
+        let (target, addr) =
+            resolve_export(source, module.clone(), export).ok_or(ScanError::ExportNotFound)?;
+        // Everything after this is relative to the forwarded module.
+        if !Rc::ptr_eq(&target, &module) {
+            info!(
+                "{}: export {} of {} is forwarded to {}, offsets and relative apply to {}",
+                sig.name, export, module.name, target.name, target.name
+            );
+        }
+        let module = target;
+        debug!(
+            "Export found in {} at: {:#X} (+ base = {:#X})",
+            module.name,
+            addr,
+            module.va(addr)
+        );
+        (module, addr)
+    } else if let Some(ref class) = sig.vtable {
+        debug!("Searching vtable: {}", class);
+        let addr = module
+            .find_vtable(class)
+            .ok_or(ScanError::VTableNotFound)?
+            .rva;
+        debug!(
+            "VTable found at: {:#X} (+ base = {:#X})",
+            addr,
+            module.va(addr)
+        );
+        (module, addr)
+    } else if let Some(ref s) = sig.string {
+        debug!("Searching string: {:?} (wide: {})", s, sig.string_wide);
+        let string = find_string(&module, s, sig.string_wide).ok_or(ScanError::StringNotFound)?;
+        debug!("String found at: {:#X}", string);
+
+        // Decode code with the bitness of the module itself.
+        let pointer_width = module
+            .pe()
+            .map_or(source.pointer_width(), |pe| pe.pointer_width);
+        let mut addr = find_xref(&module, string, pointer_width).ok_or(ScanError::XrefNotFound)?;
+        debug!(
+            "String reference found at: {:#X} (+ base = {:#X})",
+            addr,
+            module.va(addr)
+        );
+
+        if sig.function_start {
+            addr = find_function_start(&module, addr, pointer_width)
+                .ok_or(ScanError::FunctionStartNotFound)?;
+            debug!("Function start at: {:#X}", addr);
+        }
+        (module, addr)
+    } else if !sig.pattern.is_empty() {
+        let pattern = parse_pattern(sig)?;
+        debug!("Searching pattern: {}", pattern);
+        let addr = select_match(sig, &module, &module.find_pattern_all(&pattern))?;
+        debug!(
+            "Pattern found at: {:#X} (+ base = {:#X})",
+            addr,
+            module.va(addr)
+        );
+        (module, addr)
+    } else {
+        return Err(ScanError::MissingPattern);
+    };
+
+    resolve(sig, source, &module, rva)
+}
+
+/// Find all `sigs`, scanning each module only once for the pattern-only
+/// signatures. Returns the results in the order of `sigs`.
+pub fn find_signatures<S: MemorySource + ?Sized>(
+    sigs: &[&Signature],
+    source: &S,
+) -> Vec<Result<usize>> {
+    let mut res = sigs
+        .iter()
+        .map(|_| Err(ScanError::MissingPattern))
+        .collect::<Vec<_>>();
+
+    // Group the pattern-only signatures by the module they resolve to, keeping
+    // their order. Module lookups are case-insensitive, so `sig.module` is not
+    // a key.
+    let mut groups: BTreeMap<String, (Rc<Module>, Vec<usize>)> = BTreeMap::new();
+    for (i, sig) in sigs.iter().enumerate() {
+        if !is_pattern_only(sig) {
+            res[i] = find_signature(sig, source);
+            continue;
+        }
+        match load_module(sig, source) {
+            Ok(module) => groups
+                .entry(module.name.clone())
+                .or_insert_with(|| (module, vec![]))
+                .1
+                .push(i),
+            Err(e) => res[i] = Err(e),
+        }
+    }
 
+    for (name, (module, group)) in groups {
+        debug!("Scanning {} patterns in {}", group.len(), name);
+        let mut patterns = vec![];
+        let mut indices = vec![];
+        for i in group {
+            match parse_pattern(sigs[i]) {
+                Ok(pattern) => {
+                    patterns.push(pattern);
+                    indices.push(i);
+                }
+                Err(e) => res[i] = Err(e),
+            }
+        }
+
+        let found = module.find_patterns(&PatternSet::new(patterns));
+        for (i, matches) in indices.into_iter().zip(found) {
+            let sig = sigs[i];
+            res[i] = select_match(sig, &module, &matches).and_then(|rva| {
+                debug!(
+                    "{}: pattern found at: {:#X} (+ base = {:#X})",
+                    sig.name,
+                    rva,
+                    module.va(rva)
+                );
+                resolve(sig, source, &module, rva)
+            });
+        }
+    }
+    res
+}
+
+/// Apply the offsets, `rip_relative` and `extra` of `sig` to its match at
+/// `rva` in `module`.
+fn resolve<S: MemorySource + ?Sized>(
+    sig: &Signature,
+    source: &S,
+    module: &Module,
+    rva: Rva,
+) -> Result<usize> {
+    // Follow the chain on absolute addresses, pointers may leave the module.
+    let mut addr = module.va(rva);
+    let width = source.pointer_width();
     for (i, o) in sig.offsets.iter().enumerate() {
         debug!("Offset #{}: ptr: {:#X} offset: {:#X}", i, addr, o);
 
-        let pos = (addr as isize).wrapping_add(*o) as usize;
-        let data = module.data.get(pos).ok_or_else(|| {
-            debug!("WARN OOB - ptr: {:#X} module size: {:#X}", pos, module.size);
-            ScanError::OffsetOutOfBounds
-        })?;
-
-        let tmp = if process.is_wow64 {
-            let raw: u32 = unsafe { mem::transmute_copy(data) };
-            raw as usize
+        let pos = addr.offset(*o);
+        addr = if sig.live {
+            source.read_pointer(pos, width)
         } else {
-            let raw: u64 = unsafe { mem::transmute_copy(data) };
-            raw as usize
-        };
-
-        addr = tmp.wrapping_sub(module.base);
-        debug!("Offset #{}: raw: {:#X} - base => {:#X}", i, tmp, addr);
+            module.read_pointer(pos, width)
+        }
+        .map_err(|e| {
+            debug!("WARN {}", e);
+            ScanError::Read(e)
+        })?;
+        debug!("Offset #{}: raw: {:#X}", i, addr);
     }
 
     if sig.rip_relative {
@@ -89,34 +332,149 @@ pub fn find_signature(sig: &Signature, process: &Process) -> Result<usize> {
             "rip_relative: addr {:#X} + rip_offset {:#X}",
             addr, sig.rip_offset
         );
-        addr = (addr as isize).wrapping_add(sig.rip_offset) as usize;
+        addr = addr.offset(sig.rip_offset);
         debug!("rip_relative: addr = {:#X}", addr);
 
-        let rip: u32 = module
-            .get_raw(addr, true)
-            .ok_or(ScanError::RIPRelativeFailed)?;
+        let rip: i32 = module.read(addr).map_err(|e| {
+            debug!("WARN {}", e);
+            ScanError::RIPRelativeFailed
+        })?;
 
         debug!(
             "rip_relative: addr {:#X} + rip {:#X} + {:#X}",
             addr,
             rip,
-            ::std::mem::size_of::<u32>()
+            mem::size_of::<i32>()
         );
-        addr = addr.wrapping_add(rip as usize + ::std::mem::size_of::<u32>());
+        addr = addr.offset(rip as isize + mem::size_of::<i32>() as isize);
         debug!("rip_relative: addr => {:#X}", addr);
     }
 
     debug!("Adding extra {:#X}", sig.extra);
-    addr = (addr as isize).wrapping_add(sig.extra) as usize;
-    if !sig.relative {
+    addr = addr.offset(sig.extra);
+
+    if sig.value {
+        let value = source.read_pointer(addr, width).map_err(|e| {
+            debug!("WARN {}", e);
+            ScanError::Read(e)
+        })?;
+        debug!("Value at {:#X} => {:#X}", addr, value);
+        return Ok(value.0);
+    }
+
+    if sig.relative {
+        // A `live` chain may leave the module, there is no offset then.
+        let rva = module
+            .rva(addr)
+            .ok_or_else(|| ScanError::OutsideModule(addr, module.name.clone()))?;
         debug!(
-            "Not relative, addr {:#X} + base {:#X} => {:#X}",
-            addr,
-            module.base,
-            addr.wrapping_add(module.base)
+            "Relative, addr {:#X} - base {:#X} => {:#X}",
+            addr, module.base, rva
+        );
+        return Ok(rva.0);
+    }
+
+    Ok(addr.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memlib::Region;
+
+    const BASE: Va = Va(0x1000_0000);
+
+    /// A module with `AA BB 11` at +0x10, `AA BB 22` at +0x20 and
+    /// `AA BB 11` again at +0x90, in an unreadable region.
+    fn module() -> Module {
+        let mut data = vec![0u8; 0x100];
+        data[0x10..0x13].copy_from_slice(&[0xAA, 0xBB, 0x11]);
+        data[0x20..0x23].copy_from_slice(&[0xAA, 0xBB, 0x22]);
+        data[0x90..0x93].copy_from_slice(&[0xAA, 0xBB, 0x11]);
+        let regions = Region::from_ranges(data.len(), vec![(0, 0x80)]);
+        Module::with_regions("test.dll", BASE, data, regions)
+    }
+
+    fn signature(pattern: &str) -> Signature {
+        Signature {
+            name: "test".to_string(),
+            pattern: pattern.to_string(),
+            module: "test.dll".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn select(sig: &Signature) -> Result<Rva> {
+        let module = module();
+        let pattern = parse_pattern(sig)?;
+        select_match(sig, &module, &module.find_pattern_all(&pattern))
+    }
+
+    #[test]
+    fn expects_unique_match_by_default() {
+        let sig: Signature =
+            serde_json::from_str(r#"{"name": "test", "pattern": "AA BB", "module": "test.dll"}"#)
+                .unwrap();
+        assert!(sig.expect_unique);
+        assert!(Signature::default().expect_unique);
+        assert!(matches!(select(&sig), Err(ScanError::AmbiguousPattern(_))));
+    }
+
+    #[test]
+    fn selects_unique_match() {
+        assert_eq!(select(&signature("AA BB 11")).unwrap(), Rva(0x10));
+    }
+
+    #[test]
+    fn ignores_unreadable_matches() {
+        let mut sig = signature("AA BB");
+        sig.match_index = Some(2);
+        assert!(matches!(
+            select(&sig),
+            Err(ScanError::MatchIndexOutOfRange { index: 2, count: 2 })
+        ));
+    }
+
+    #[test]
+    fn formats_ambiguous_matches() {
+        let err = select(&signature("AA BB")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Pattern is not unique, 2 matches at 0x10000010, 0x10000020"
         );
-        addr = addr.wrapping_add(module.base);
     }
 
-    Ok(addr)
+    #[test]
+    fn selects_first_match_if_not_unique() {
+        let mut sig = signature("AA BB");
+        sig.expect_unique = false;
+        assert_eq!(select(&sig).unwrap(), Rva(0x10));
+    }
+
+    #[test]
+    fn selects_match_index() {
+        let mut sig = signature("AA BB");
+        sig.match_index = Some(1);
+        assert_eq!(select(&sig).unwrap(), Rva(0x20));
+    }
+
+    #[test]
+    fn rejects_match_index_out_of_range() {
+        let mut sig = signature("AA BB");
+        sig.match_index = Some(5);
+        let err = select(&sig).unwrap_err();
+        assert!(matches!(
+            err,
+            ScanError::MatchIndexOutOfRange { index: 5, count: 2 }
+        ));
+        assert_eq!(err.to_string(), "match_index 5 out of range, 2 matches");
+    }
+
+    #[test]
+    fn reports_missing_pattern() {
+        assert!(matches!(
+            select(&signature("CC DD")),
+            Err(ScanError::PatternNotFound)
+        ));
+    }
 }